@@ -0,0 +1,11 @@
+-- Timestamp of the newest conversation posted to each feed
+ALTER TABLE heycafe_feeds ADD COLUMN last_post_timestamp INTEGER NOT NULL DEFAULT 0;
+
+-- Per guild settings, created when the bot joins a guild
+CREATE TABLE IF NOT EXISTS guild_settings (
+    id INTEGER PRIMARY KEY NOT NULL,
+    guild_id INTEGER NOT NULL,
+    feed_settings_required_roleid INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS guild_settings_guild_id ON guild_settings (guild_id);
@@ -0,0 +1,8 @@
+-- Feed health, used to disable feeds that keep failing
+ALTER TABLE heycafe_feeds ADD COLUMN enabled BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE heycafe_feeds ADD COLUMN consecutive_failures INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE heycafe_feeds ADD COLUMN last_error TEXT;
+ALTER TABLE heycafe_feeds ADD COLUMN last_success_at INTEGER;
+
+-- Channel for bot notices, 0 posts them in the affected feed's channel
+ALTER TABLE guild_settings ADD COLUMN admin_channel_id INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- Unix timestamp before which a failing feed is not checked again
+ALTER TABLE heycafe_feeds ADD COLUMN retry_after INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- Seconds between checks, NULL falls back to the guild setting and then the bot default
+ALTER TABLE guild_settings ADD COLUMN poll_interval_secs INTEGER;
+ALTER TABLE heycafe_feeds ADD COLUMN poll_interval_secs INTEGER;
+
+-- Unix timestamp of the feed's last check, used to tell when it is due
+ALTER TABLE heycafe_feeds ADD COLUMN last_checked_at INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- Drop duplicate subscriptions, keeping the oldest one
+DELETE FROM heycafe_feeds WHERE id NOT IN (
+    SELECT MIN(id) FROM heycafe_feeds GROUP BY guild_id, channel_id, feed_type, heycafe_id, tag_id
+);
+
+CREATE UNIQUE INDEX heycafe_feeds_subscription ON heycafe_feeds (guild_id, channel_id, feed_type, heycafe_id, tag_id);
@@ -0,0 +1,4 @@
+-- Guild wide defaults, changed with /settings
+ALTER TABLE guild_settings ADD COLUMN default_mention_role_id INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE guild_settings ADD COLUMN embed_color INTEGER NOT NULL DEFAULT 6311896;
+ALTER TABLE guild_settings ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
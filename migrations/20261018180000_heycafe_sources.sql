@@ -0,0 +1,23 @@
+-- Cached names of followed users and cafes, so listing feeds doesn't call Hey.Cafe
+CREATE TABLE heycafe_sources (
+    id INTEGER PRIMARY KEY NOT NULL,
+    feed_type TEXT NOT NULL,
+    heycafe_id TEXT NOT NULL,
+    alias TEXT NOT NULL DEFAULT '',
+    display_name TEXT NOT NULL DEFAULT '',
+    avatar TEXT,
+    updated_at INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE UNIQUE INDEX heycafe_sources_source ON heycafe_sources (feed_type, heycafe_id);
+
+CREATE TABLE heycafe_tags (
+    id INTEGER PRIMARY KEY NOT NULL,
+    feed_type TEXT NOT NULL,
+    heycafe_id TEXT NOT NULL,
+    tag_id TEXT NOT NULL,
+    name TEXT NOT NULL DEFAULT '',
+    emoji TEXT NOT NULL DEFAULT ''
+);
+
+CREATE UNIQUE INDEX heycafe_tags_tag ON heycafe_tags (feed_type, heycafe_id, tag_id);
@@ -0,0 +1,29 @@
+-- Subscriptions carry lists of tags to include and exclude instead of a single tag_id.
+-- Tag ids are stored comma separated, an empty include list means every tag.
+ALTER TABLE heycafe_feeds ADD COLUMN include_tag_ids TEXT NOT NULL DEFAULT '';
+ALTER TABLE heycafe_feeds ADD COLUMN exclude_tag_ids TEXT NOT NULL DEFAULT '';
+
+-- Merge subscriptions to different tags of the same source in the same channel into the oldest one
+UPDATE heycafe_feeds AS keep SET include_tag_ids = CASE
+    WHEN EXISTS (
+        SELECT 1 FROM heycafe_feeds AS other
+        WHERE other.guild_id = keep.guild_id AND other.channel_id = keep.channel_id AND other.feed_type = keep.feed_type AND other.heycafe_id = keep.heycafe_id
+        AND other.tag_id = 'none'
+    ) THEN ''
+    ELSE (
+        SELECT group_concat(other.tag_id, ',') FROM heycafe_feeds AS other
+        WHERE other.guild_id = keep.guild_id AND other.channel_id = keep.channel_id AND other.feed_type = keep.feed_type AND other.heycafe_id = keep.heycafe_id
+    )
+END
+WHERE keep.id = (
+    SELECT MIN(other.id) FROM heycafe_feeds AS other
+    WHERE other.guild_id = keep.guild_id AND other.channel_id = keep.channel_id AND other.feed_type = keep.feed_type AND other.heycafe_id = keep.heycafe_id
+);
+
+DELETE FROM heycafe_feeds WHERE id NOT IN (
+    SELECT MIN(id) FROM heycafe_feeds GROUP BY guild_id, channel_id, feed_type, heycafe_id
+);
+
+DROP INDEX heycafe_feeds_subscription;
+ALTER TABLE heycafe_feeds DROP COLUMN tag_id;
+CREATE UNIQUE INDEX heycafe_feeds_subscription ON heycafe_feeds (guild_id, channel_id, feed_type, heycafe_id);
@@ -0,0 +1,10 @@
+-- Keyword and regex rules that decide which conversations a feed posts
+CREATE TABLE feed_filters (
+    id INTEGER PRIMARY KEY NOT NULL,
+    feed_id INTEGER NOT NULL REFERENCES heycafe_feeds (id) ON DELETE CASCADE,
+    action TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    pattern TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX feed_filters_rule ON feed_filters (feed_id, action, kind, pattern);
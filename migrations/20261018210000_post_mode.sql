@@ -0,0 +1,2 @@
+-- Which posts of a followed user are shared: 'personal', 'cafe' or 'both'
+ALTER TABLE heycafe_feeds ADD COLUMN post_mode TEXT NOT NULL DEFAULT 'personal';
@@ -0,0 +1,32 @@
+// Shared command checks
+use crate::{Context, Error};
+use poise::serenity_prelude as serenity;
+use serenity::RoleId;
+
+// CHECK - Manage Channels, or the role set with /settings managerrole
+pub async fn can_manage_feeds(ctx: Context<'_>) -> Result<bool, Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(false);
+    };
+    let Some(member) = ctx.author_member().await else {
+        return Ok(false);
+    };
+
+    if member.permissions.is_some_and(|permissions| permissions.manage_channels()) {
+        return Ok(true);
+    }
+
+    let guild_id = *guild_id.as_u64() as i64;
+    let settings = sqlx::query!("SELECT feed_settings_required_roleid FROM guild_settings WHERE guild_id = ?", guild_id)
+        .fetch_optional(&ctx.data().database)
+        .await?;
+
+    let allowed = match settings {
+        Some(settings) if settings.feed_settings_required_roleid != 0 => {
+            member.roles.contains(&RoleId(settings.feed_settings_required_roleid as u64))
+        },
+        _ => false
+    };
+
+    Ok(allowed)
+}
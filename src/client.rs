@@ -0,0 +1,397 @@
+// Typed client for the Hey.Cafe API
+use serde::{Deserialize, Deserializer, de::{DeserializeOwned, Error as _}};
+use serde_json::Value;
+use std::sync::Arc;
+use crate::ratelimit::TokenBucket;
+
+const API_BASE: &str = "https://endpoint.hey.cafe/api";
+
+// Errors returned by the Hey.Cafe client
+#[derive(Debug)]
+pub enum ApiError {
+    Timeout,
+    Unavailable(reqwest::StatusCode),
+    Request(reqwest::Error),
+    Decode(serde_json::Error),
+    Api(String),
+    NotFound,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::Timeout => write!(f, "Hey.Cafe took too long to respond!"),
+            ApiError::Unavailable(status) => write!(f, "Hey.Cafe is unavailable right now ({status})!"),
+            ApiError::Request(_) => write!(f, "There was an error requesting information!"),
+            ApiError::Decode(_) => write!(f, "There was an error handling information!"),
+            ApiError::Api(message) => write!(f, "Hey.Cafe returned an error: {message}"),
+            ApiError::NotFound => write!(f, "No information was found!"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Request(err) => Some(err),
+            ApiError::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            ApiError::Timeout
+        } else {
+            ApiError::Request(err)
+        }
+    }
+}
+
+// API models
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Account {
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub id: String,
+    #[serde(default)]
+    pub alias: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, deserialize_with = "false_as_none")]
+    pub avatar: Option<String>,
+    #[serde(default, deserialize_with = "list_or_false")]
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Cafe {
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub id: String,
+    #[serde(default)]
+    pub alias: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, deserialize_with = "false_as_none")]
+    pub avatar: Option<String>,
+    #[serde(default, deserialize_with = "list_or_false")]
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Tag {
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub emoji: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Attachment {
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub file: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Conversation {
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    #[serde(default, deserialize_with = "timestamp")]
+    pub date_created: i64,
+    #[serde(default)]
+    pub contents: String,
+    #[serde(default, deserialize_with = "false_as_none")]
+    pub account: Option<Account>,
+    #[serde(default, deserialize_with = "false_as_none")]
+    pub cafe: Option<Cafe>,
+    #[serde(default, deserialize_with = "false_as_none")]
+    pub tag: Option<Tag>,
+    #[serde(default, deserialize_with = "list_or_false")]
+    pub attachments: Vec<Attachment>,
+}
+
+impl Conversation {
+    // First image attached to the conversation, if any
+    pub fn image_url(&self) -> Option<&str> {
+        self.attachments.iter()
+            .find(|attachment| attachment.kind == "image" && !attachment.file.is_empty())
+            .map(|attachment| attachment.file.as_str())
+    }
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    system_api_error: Value,
+    #[serde(default)]
+    response_data: Value,
+}
+
+#[derive(Deserialize)]
+struct ConversationList {
+    #[serde(default, deserialize_with = "list_or_false")]
+    conversations: Vec<Conversation>,
+}
+
+// Hey.Cafe API client
+#[derive(Debug, Clone)]
+pub struct HeyCafeClient {
+    http: reqwest::Client,
+    rate_limit: Option<Arc<TokenBucket>>,
+}
+
+impl HeyCafeClient {
+    pub fn new(http: reqwest::Client) -> Self {
+        Self { http, rate_limit: None }
+    }
+
+    /// Limits requests made through this client, and any clones of it, to the given bucket.
+    pub fn with_rate_limit(mut self, bucket: TokenBucket) -> Self {
+        self.rate_limit = Some(Arc::new(bucket));
+        self
+    }
+
+    /// Looks up an account by alias or id.
+    pub async fn account_info(&self, query: &str) -> Result<Account, ApiError> {
+        self.request("account_info", &[("query", query), ("convert_numeric", "tags")]).await
+    }
+
+    /// Looks up a cafe by alias or id.
+    pub async fn cafe_info(&self, query: &str) -> Result<Cafe, ApiError> {
+        self.request("cafe_info", &[("query", query), ("convert_numeric", "tags")]).await
+    }
+
+    /// Conversations posted by an account, newest first, optionally limited to one tag.
+    pub async fn account_conversations(&self, query: &str, tag: Option<&str>, count: u32, offset: u32) -> Result<Vec<Conversation>, ApiError> {
+        self.conversations("account_conversations", query, tag, count, offset).await
+    }
+
+    /// Conversations posted in a cafe, newest first, optionally limited to one tag.
+    pub async fn cafe_conversations(&self, query: &str, tag: Option<&str>, count: u32, offset: u32) -> Result<Vec<Conversation>, ApiError> {
+        self.conversations("cafe_conversations", query, tag, count, offset).await
+    }
+
+    async fn conversations(&self, endpoint: &str, query: &str, tag: Option<&str>, count: u32, offset: u32) -> Result<Vec<Conversation>, ApiError> {
+        let count = count.to_string();
+        let offset = offset.to_string();
+        let mut params = vec![("query", query), ("convert_numeric", "conversations"), ("count", count.as_str()), ("offset", offset.as_str())];
+        if let Some(tag) = tag {
+            params.push(("tag", tag));
+        }
+
+        let list: ConversationList = self.request(endpoint, &params).await?;
+        Ok(list.conversations)
+    }
+
+    async fn request<T: DeserializeOwned>(&self, endpoint: &str, params: &[(&str, &str)]) -> Result<T, ApiError> {
+        if let Some(bucket) = &self.rate_limit {
+            bucket.acquire().await;
+        }
+
+        let response = self.http.get(format!("{API_BASE}/{endpoint}"))
+            .query(params)
+            .send()
+            .await?;
+
+        // Outages and rate limits usually come back as an HTML page, not an API error
+        let status = response.status();
+        if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(ApiError::Unavailable(status));
+        }
+
+        let body = response.bytes().await?;
+        let envelope: Envelope = match serde_json::from_slice(&body) {
+            Ok(envelope) => envelope,
+            Err(_) if !status.is_success() => return Err(ApiError::Api(format!("HTTP {status}"))),
+            Err(err) => return Err(ApiError::Decode(err)),
+        };
+        match envelope.system_api_error {
+            Value::Bool(_) | Value::Null => {},
+            Value::String(message) => return Err(ApiError::Api(message)),
+            other => return Err(ApiError::Api(other.to_string())),
+        }
+
+        match envelope.response_data {
+            Value::Null | Value::Bool(_) => Err(ApiError::NotFound),
+            data => serde_json::from_value(data).map_err(ApiError::Decode),
+        }
+    }
+}
+
+// Deserialize helpers - Hey.Cafe uses `false` for missing objects and lists
+fn false_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Null | Value::Bool(_) => Ok(None),
+        value => serde_json::from_value(value).map(Some).map_err(D::Error::custom),
+    }
+}
+
+fn list_or_false<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let items: Vec<Value> = match Value::deserialize(deserializer)? {
+        Value::Array(items) => items,
+        Value::Object(map) => map.into_iter().map(|(_, value)| value).collect(),
+        _ => return Ok(Vec::new()),
+    };
+
+    // One item Hey.Cafe sends oddly shouldn't fail the whole list
+    let list = items.into_iter()
+        .filter_map(|item| match serde_json::from_value(item) {
+            Ok(item) => Some(item),
+            Err(err) => {
+                println!("[API] Skipping an item that couldn't be read - Error: {err}");
+                None
+            }
+        })
+        .collect();
+
+    Ok(list)
+}
+
+fn string_or_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(value) => Ok(value),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Null | Value::Bool(_) => Ok(String::new()),
+        other => Err(D::Error::custom(format!("expected string or number, found {other}"))),
+    }
+}
+
+fn timestamp<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(value) => value.parse().map_err(D::Error::custom),
+        Value::Number(value) => value.as_i64().ok_or_else(|| D::Error::custom("timestamp out of range")),
+        _ => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn conversation(value: Value) -> Conversation {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn false_objects_are_none() {
+        let conversation = conversation(json!({
+            "id": "1",
+            "account": { "id": 5, "alias": "someone", "name": "Someone", "avatar": false, "tags": false },
+            "cafe": false,
+            "tag": false,
+            "attachments": false
+        }));
+
+        let account = conversation.account.unwrap();
+        assert_eq!(account.avatar, None);
+        assert!(account.tags.is_empty());
+        assert!(conversation.cafe.is_none());
+        assert!(conversation.tag.is_none());
+        assert!(conversation.attachments.is_empty());
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let conversation = conversation(json!({ "id": 1 }));
+        assert_eq!(conversation.date_created, 0);
+        assert_eq!(conversation.contents, "");
+        assert!(conversation.account.is_none());
+        assert!(conversation.attachments.is_empty());
+    }
+
+    #[test]
+    fn ids_can_be_strings_or_numbers() {
+        assert_eq!(conversation(json!({ "id": "12345" })).id, "12345");
+        assert_eq!(conversation(json!({ "id": 12345 })).id, "12345");
+
+        let tag: Tag = serde_json::from_value(json!({ "id": 7, "name": "News", "emoji": "📰" })).unwrap();
+        assert_eq!(tag.id, "7");
+
+        let tag: Tag = serde_json::from_value(json!({ "id": false })).unwrap();
+        assert_eq!(tag.id, "");
+        assert!(serde_json::from_value::<Tag>(json!({ "id": [1] })).is_err());
+    }
+
+    #[test]
+    fn timestamps_can_be_strings_or_numbers() {
+        assert_eq!(conversation(json!({ "id": 1, "date_created": "1690000000" })).date_created, 1690000000);
+        assert_eq!(conversation(json!({ "id": 1, "date_created": 1690000000 })).date_created, 1690000000);
+        assert_eq!(conversation(json!({ "id": 1, "date_created": false })).date_created, 0);
+        assert!(serde_json::from_value::<Conversation>(json!({ "id": 1, "date_created": "yesterday" })).is_err());
+    }
+
+    #[test]
+    fn attachments_can_be_a_list_or_a_map() {
+        let listed = conversation(json!({
+            "id": 1,
+            "attachments": [{ "type": "file", "file": "a.zip" }, { "type": "image", "file": "b.png" }]
+        }));
+        assert_eq!(listed.attachments.len(), 2);
+        assert_eq!(listed.image_url(), Some("b.png"));
+
+        let mapped = conversation(json!({
+            "id": 1,
+            "attachments": { "0": { "type": "image", "file": "c.png" }, "1": { "type": "video", "file": "d.mp4" } }
+        }));
+        assert_eq!(mapped.attachments.len(), 2);
+        assert_eq!(mapped.image_url(), Some("c.png"));
+    }
+
+    #[test]
+    fn cafe_conversations_keep_the_cafe() {
+        let conversation = conversation(json!({
+            "id": 1,
+            "cafe": { "id": "9", "alias": "somecafe", "name": "Some Cafe", "avatar": "https://hey.cafe/a.png" },
+            "tag": { "id": "3", "name": "News", "emoji": "📰" }
+        }));
+
+        let cafe = conversation.cafe.unwrap();
+        assert_eq!(cafe.alias, "somecafe");
+        assert_eq!(cafe.avatar.as_deref(), Some("https://hey.cafe/a.png"));
+        assert_eq!(conversation.tag.unwrap().id, "3");
+    }
+
+    #[test]
+    fn bad_conversations_are_skipped() {
+        let list: ConversationList = serde_json::from_value(json!({
+            "conversations": [
+                { "id": 1, "date_created": "1690000000" },
+                { "id": 2, "date_created": "not a time" },
+                { "contents": "no id" },
+                { "id": 4, "account": false }
+            ]
+        })).unwrap();
+
+        let ids: Vec<&str> = list.conversations.iter().map(|conversation| conversation.id.as_str()).collect();
+        assert_eq!(ids, ["1", "4"]);
+    }
+
+    #[test]
+    fn false_lists_are_empty() {
+        let list: ConversationList = serde_json::from_value(json!({ "conversations": false })).unwrap();
+        assert!(list.conversations.is_empty());
+
+        let list: ConversationList = serde_json::from_value(json!({})).unwrap();
+        assert!(list.conversations.is_empty());
+    }
+}
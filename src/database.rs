@@ -0,0 +1,63 @@
+// Adopts databases set up before migrations were tracked
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+use sqlx::SqlitePool;
+
+// Creates heycafe_feeds
+const BASELINE_VERSION: i64 = 20230729174359;
+
+// Adds heycafe_feeds.last_post_timestamp and guild_settings, which older bots made by hand
+const FEED_TIMESTAMPS_VERSION: i64 = 20261018120000;
+
+// FUNCTION - Marks the migrations an existing database already has as applied, so they don't run twice
+pub async fn adopt_existing_schema(database: &SqlitePool, migrator: &Migrator) -> Result<(), MigrateError> {
+    let feeds_table: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'heycafe_feeds'")
+        .fetch_one(database)
+        .await?;
+    if feeds_table == 0 {
+        return Ok(());
+    }
+
+    let mut conn = database.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied: Vec<i64> = conn.list_applied_migrations().await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect();
+
+    if !applied.contains(&BASELINE_VERSION) {
+        record_applied(database, migrator, BASELINE_VERSION).await?;
+    }
+
+    // SQLite can't add a column only if it's missing, so check for it here instead
+    let timestamp_column: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info('heycafe_feeds') WHERE name = 'last_post_timestamp'")
+        .fetch_one(database)
+        .await?;
+    if timestamp_column > 0 && !applied.contains(&FEED_TIMESTAMPS_VERSION) {
+        sqlx::query("CREATE TABLE IF NOT EXISTS guild_settings (id INTEGER PRIMARY KEY NOT NULL, guild_id INTEGER NOT NULL, feed_settings_required_roleid INTEGER NOT NULL DEFAULT 0)")
+            .execute(database)
+            .await?;
+        sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS guild_settings_guild_id ON guild_settings (guild_id)")
+            .execute(database)
+            .await?;
+        record_applied(database, migrator, FEED_TIMESTAMPS_VERSION).await?;
+    }
+
+    Ok(())
+}
+
+// FUNCTION - Records a migration as applied without running it
+async fn record_applied(database: &SqlitePool, migrator: &Migrator, version: i64) -> Result<(), MigrateError> {
+    let Some(migration) = migrator.iter().find(|migration| migration.version == version) else {
+        return Err(MigrateError::VersionMissing(version));
+    };
+
+    sqlx::query("INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES (?, ?, TRUE, ?, -1)")
+        .bind(migration.version)
+        .bind(&*migration.description)
+        .bind(&*migration.checksum)
+        .execute(database)
+        .await?;
+
+    println!("[DATABASE] Existing schema found, marked migration {} ({}) as applied", migration.version, migration.description);
+    Ok(())
+}
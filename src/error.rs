@@ -0,0 +1,115 @@
+// Error type shared by the library, commands and the poller
+use poise::serenity_prelude as serenity;
+use crate::client::ApiError;
+
+#[derive(Debug)]
+pub enum BotCafeError {
+    Network(reqwest::Error),
+    Timeout,
+    Unavailable(reqwest::StatusCode),
+    Decode(serde_json::Error),
+    ApiError(String),
+    NotFound,
+    Database(sqlx::Error),
+    Discord(Box<serenity::Error>),
+    InvalidInput(String),
+}
+
+impl BotCafeError {
+    /// Whether retrying the same operation later could succeed. Only errors that are not temporary count towards disabling a feed.
+    pub fn is_temporary(&self) -> bool {
+        match self {
+            // Responses that can't be read are usually Hey.Cafe having trouble, not the feed being broken
+            BotCafeError::Network(_)
+            | BotCafeError::Timeout
+            | BotCafeError::Unavailable(_)
+            | BotCafeError::Decode(_)
+            | BotCafeError::Database(_) => true,
+            BotCafeError::Discord(err) => match err.as_ref() {
+                serenity::Error::Http(err) => match err.status_code() {
+                    Some(status) => status.is_server_error() || status.as_u16() == 429,
+                    None => true,
+                },
+                _ => true,
+            },
+            BotCafeError::ApiError(_)
+            | BotCafeError::NotFound
+            | BotCafeError::InvalidInput(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for BotCafeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BotCafeError::Network(err) => write!(f, "Network error: {err}"),
+            BotCafeError::Timeout => write!(f, "Request to Hey.Cafe timed out"),
+            BotCafeError::Unavailable(status) => write!(f, "Hey.Cafe is unavailable: {status}"),
+            BotCafeError::Decode(err) => write!(f, "Failed to decode Hey.Cafe response: {err}"),
+            BotCafeError::ApiError(message) => write!(f, "Hey.Cafe API error: {message}"),
+            BotCafeError::NotFound => write!(f, "Not found on Hey.Cafe"),
+            BotCafeError::Database(err) => write!(f, "Database error: {err}"),
+            BotCafeError::Discord(err) => write!(f, "Discord error: {err}"),
+            BotCafeError::InvalidInput(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for BotCafeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BotCafeError::Network(err) => Some(err),
+            BotCafeError::Decode(err) => Some(err),
+            BotCafeError::Database(err) => Some(err),
+            BotCafeError::Discord(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ApiError> for BotCafeError {
+    fn from(err: ApiError) -> Self {
+        match err {
+            ApiError::Timeout => BotCafeError::Timeout,
+            ApiError::Unavailable(status) => BotCafeError::Unavailable(status),
+            ApiError::Request(err) => BotCafeError::Network(err),
+            ApiError::Decode(err) => BotCafeError::Decode(err),
+            ApiError::Api(message) => BotCafeError::ApiError(message),
+            ApiError::NotFound => BotCafeError::NotFound,
+        }
+    }
+}
+
+impl From<sqlx::Error> for BotCafeError {
+    fn from(err: sqlx::Error) -> Self {
+        BotCafeError::Database(err)
+    }
+}
+
+impl From<serenity::Error> for BotCafeError {
+    fn from(err: serenity::Error) -> Self {
+        BotCafeError::Discord(Box::new(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outages_are_temporary() {
+        let decode = serde_json::from_str::<serde_json::Value>("<html>").unwrap_err();
+
+        assert!(BotCafeError::Timeout.is_temporary());
+        assert!(BotCafeError::Unavailable(reqwest::StatusCode::BAD_GATEWAY).is_temporary());
+        assert!(BotCafeError::Unavailable(reqwest::StatusCode::TOO_MANY_REQUESTS).is_temporary());
+        assert!(BotCafeError::Decode(decode).is_temporary());
+    }
+
+    #[test]
+    fn missing_sources_are_permanent() {
+        assert!(!BotCafeError::NotFound.is_temporary());
+        assert!(!BotCafeError::ApiError(String::from("Account not found")).is_temporary());
+        assert!(!BotCafeError::InvalidInput(String::from("bad alias")).is_temporary());
+    }
+}
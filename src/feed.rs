@@ -0,0 +1,270 @@
+// Kinds of Hey.Cafe feeds and the users or cafes they follow
+use crate::BotCafeError;
+
+/// Whether a feed follows a user or a cafe. Stored as "user" or "cafe" in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, poise::ChoiceParameter, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum FeedType {
+    #[name = "user"]
+    User,
+    #[name = "cafe"]
+    Cafe,
+}
+
+impl FeedType {
+    /// Character written before aliases of this type, like @user or !cafe.
+    pub fn prefix(&self) -> char {
+        match self {
+            FeedType::User => '@',
+            FeedType::Cafe => '!',
+        }
+    }
+}
+
+/// Which posts of a followed user a feed shares. Stored as "personal", "cafe" or "both" in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, poise::ChoiceParameter, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum PostMode {
+    /// Posts on the user's own profile
+    #[default]
+    #[name = "personal"]
+    Personal,
+    /// Posts the user makes inside cafes
+    #[name = "cafe"]
+    Cafe,
+    #[name = "both"]
+    Both,
+}
+
+impl PostMode {
+    /// Whether a user's conversation is shared, given if it was posted inside a cafe.
+    pub fn allows(&self, in_cafe: bool) -> bool {
+        match self {
+            PostMode::Personal => !in_cafe,
+            PostMode::Cafe => in_cafe,
+            PostMode::Both => true,
+        }
+    }
+}
+
+/// A user or cafe picked by a command, parsed from an alias like @user, !cafe or a hey.cafe link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedSource {
+    pub feed_type: FeedType,
+    pub alias: String,
+}
+
+impl FeedSource {
+    /// Parses an alias, using `source` when the alias doesn't say whether it's a user or a cafe.
+    pub fn parse(input: &str, source: Option<FeedType>) -> Result<FeedSource, BotCafeError> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(BotCafeError::InvalidInput(String::from("Give the alias of a user or cafe, like @user or !cafe!")));
+        }
+
+        let (found_type, alias) = match strip_hey_cafe_url(input) {
+            Some(path) => {
+                let segment = path.split(['/', '?', '#']).next().unwrap_or_default();
+                match split_prefix(segment) {
+                    (Some(found_type), alias) => (Some(found_type), alias),
+                    (None, _) => return Err(BotCafeError::InvalidInput(format!("\"{input}\" isn't a link to a Hey.Cafe user or cafe!")))
+                }
+            },
+            None => split_prefix(input)
+        };
+
+        let feed_type = match (found_type, source) {
+            (Some(found_type), Some(source)) if found_type != source => {
+                return Err(BotCafeError::InvalidInput(format!("\"{input}\" is a {found_type}, but the source was set to {source}!")));
+            },
+            (Some(found_type), _) => found_type,
+            (None, Some(source)) => source,
+            (None, None) => {
+                return Err(BotCafeError::InvalidInput(format!("Start \"{input}\" with @ for a user or ! for a cafe, or pick a source!")));
+            }
+        };
+
+        let valid = !alias.is_empty() && alias.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid {
+            return Err(BotCafeError::InvalidInput(format!("\"{input}\" isn't a valid Hey.Cafe alias!")));
+        }
+
+        Ok(FeedSource { feed_type, alias: alias.to_string() })
+    }
+}
+
+impl std::fmt::Display for FeedSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.feed_type.prefix(), self.alias)
+    }
+}
+
+// Path of a hey.cafe link, without the scheme and host
+fn strip_hey_cafe_url(input: &str) -> Option<&str> {
+    let rest = input.strip_prefix("https://")
+        .or_else(|| input.strip_prefix("http://"))
+        .unwrap_or(input);
+    let rest = rest.strip_prefix("www.").unwrap_or(rest);
+
+    rest.strip_prefix("hey.cafe/")
+}
+
+// Splits the @ or ! off an alias
+fn split_prefix(alias: &str) -> (Option<FeedType>, &str) {
+    if let Some(alias) = alias.strip_prefix('@') {
+        (Some(FeedType::User), alias)
+    } else if let Some(alias) = alias.strip_prefix('!') {
+        (Some(FeedType::Cafe), alias)
+    } else {
+        (None, alias)
+    }
+}
+
+/// Tags a subscription is limited to and tags it skips, stored as comma separated ids.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl TagFilter {
+    /// Reads a filter from the `include_tag_ids` and `exclude_tag_ids` columns.
+    pub fn from_columns(include: &str, exclude: &str) -> TagFilter {
+        TagFilter { include: split_ids(include), exclude: split_ids(exclude) }
+    }
+
+    /// Whether a conversation with the given tag should be posted. Untagged conversations only pass when no tags are included.
+    pub fn allows(&self, tag_id: Option<&str>) -> bool {
+        match tag_id {
+            Some(tag_id) => {
+                (self.include.is_empty() || self.include.iter().any(|id| id == tag_id))
+                    && !self.exclude.iter().any(|id| id == tag_id)
+            },
+            None => self.include.is_empty()
+        }
+    }
+
+    /// Whether every conversation passes.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+/// Joins tag ids for storing in a tag list column.
+pub fn join_ids(ids: &[String]) -> String {
+    ids.join(",")
+}
+
+fn split_ids(ids: &str) -> Vec<String> {
+    ids.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(input: &str, source: Option<FeedType>) -> FeedSource {
+        FeedSource::parse(input, source).unwrap()
+    }
+
+    fn source(feed_type: FeedType, alias: &str) -> FeedSource {
+        FeedSource { feed_type, alias: alias.to_string() }
+    }
+
+    #[test]
+    fn parses_prefixed_aliases() {
+        assert_eq!(parsed("@someone", None), source(FeedType::User, "someone"));
+        assert_eq!(parsed("!somecafe", None), source(FeedType::Cafe, "somecafe"));
+        assert_eq!(parsed("  @some_one.x-y  ", None), source(FeedType::User, "some_one.x-y"));
+    }
+
+    #[test]
+    fn uses_source_for_bare_aliases() {
+        assert_eq!(parsed("someone", Some(FeedType::User)), source(FeedType::User, "someone"));
+        assert_eq!(parsed("somecafe", Some(FeedType::Cafe)), source(FeedType::Cafe, "somecafe"));
+        assert!(FeedSource::parse("someone", None).is_err());
+    }
+
+    #[test]
+    fn prefix_and_source_must_agree() {
+        assert_eq!(parsed("@someone", Some(FeedType::User)), source(FeedType::User, "someone"));
+        assert!(FeedSource::parse("@someone", Some(FeedType::Cafe)).is_err());
+        assert!(FeedSource::parse("!somecafe", Some(FeedType::User)).is_err());
+    }
+
+    #[test]
+    fn parses_links() {
+        assert_eq!(parsed("https://hey.cafe/@someone", None), source(FeedType::User, "someone"));
+        assert_eq!(parsed("http://hey.cafe/!somecafe", None), source(FeedType::Cafe, "somecafe"));
+        assert_eq!(parsed("https://www.hey.cafe/!somecafe/", None), source(FeedType::Cafe, "somecafe"));
+        assert_eq!(parsed("www.hey.cafe/@someone?tab=posts", None), source(FeedType::User, "someone"));
+        assert_eq!(parsed("hey.cafe/@someone#top", None), source(FeedType::User, "someone"));
+    }
+
+    #[test]
+    fn links_must_point_at_a_user_or_cafe() {
+        assert!(FeedSource::parse("https://hey.cafe/conversation/12345", None).is_err());
+        assert!(FeedSource::parse("https://hey.cafe/", Some(FeedType::User)).is_err());
+        assert!(FeedSource::parse("https://hey.cafe/@someone", Some(FeedType::Cafe)).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_and_invalid_aliases() {
+        assert!(FeedSource::parse("", Some(FeedType::User)).is_err());
+        assert!(FeedSource::parse("   ", None).is_err());
+        assert!(FeedSource::parse("@", None).is_err());
+        assert!(FeedSource::parse("@some one", None).is_err());
+        assert!(FeedSource::parse("!cafe<script>", None).is_err());
+        assert!(FeedSource::parse("https://example.com/@someone", None).is_err());
+    }
+
+    #[test]
+    fn empty_tag_filter_allows_everything() {
+        let filter = TagFilter::from_columns("", "");
+        assert!(filter.is_empty());
+        assert!(filter.allows(Some("1")));
+        assert!(filter.allows(None));
+    }
+
+    #[test]
+    fn included_tags_drop_untagged_conversations() {
+        let filter = TagFilter::from_columns("1,2", "");
+        assert!(filter.allows(Some("1")));
+        assert!(filter.allows(Some("2")));
+        assert!(!filter.allows(Some("3")));
+        assert!(!filter.allows(None));
+    }
+
+    #[test]
+    fn excluded_tags_keep_untagged_conversations() {
+        let filter = TagFilter::from_columns("", "3");
+        assert!(filter.allows(Some("1")));
+        assert!(!filter.allows(Some("3")));
+        assert!(filter.allows(None));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filter = TagFilter::from_columns("1,3", "3");
+        assert!(filter.allows(Some("1")));
+        assert!(!filter.allows(Some("3")));
+    }
+
+    #[test]
+    fn tag_columns_round_trip() {
+        let filter = TagFilter::from_columns(" 1, 2,,3 ", "");
+        assert_eq!(filter.include, ["1", "2", "3"]);
+        assert_eq!(join_ids(&filter.include), "1,2,3");
+        assert_eq!(TagFilter::from_columns(&join_ids(&filter.include), ""), filter);
+    }
+
+    #[test]
+    fn displays_with_prefix() {
+        assert_eq!(source(FeedType::User, "someone").to_string(), "@someone");
+        assert_eq!(source(FeedType::Cafe, "somecafe").to_string(), "!somecafe");
+    }
+}
@@ -1,12 +1,15 @@
-use crate::{Context, Error};
+use crate::{poller, sources, sources::SourceInfo, Context, Error, GuildSettings, UserFeed};
 use poise::serenity_prelude as serenity;
-use serde_json::Value;
-use botcafe::grab_feed_data;
+use botcafe::client::{Conversation, HeyCafeClient, Tag};
+use botcafe::{feed, BotCafeError, FeedSource, FeedType, PostMode, TagFilter};
+
+// Conversations looked through when backfilling a feed limited by tags
+const BACKFILL_SEARCH: u32 = 50;
 
 // PARENT
 #[poise::command(
     slash_command,
-    subcommands("add", "remove")
+    subcommands("add", "remove", "edit", "crate::filters::filter")
 )]
 pub async fn feed(_: Context<'_>) -> Result<(), Error> {
     Ok(())
@@ -15,73 +18,162 @@ pub async fn feed(_: Context<'_>) -> Result<(), Error> {
 /// Add a feed to listen to.
 #[poise::command(
     slash_command,
-    required_permissions = "MANAGE_CHANNELS",
+    check = "crate::checks::can_manage_feeds",
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn add(
     ctx: Context<'_>,
-    #[description = "Alias of the user or cafe."]
-    #[max_length = 30] mut alias: String,
+    #[description = "Alias or hey.cafe link of the user or cafe."]
+    #[max_length = 100] alias: String,
 
     #[description = "Channel to post feeds."]
     #[rename = "channel"] feed_channel: serenity::Channel,
 
+    #[description = "Whether the alias is a user or a cafe, if it doesn't start with @ or !."]
+    #[rename = "source"] source_type: Option<FeedType>,
+
     #[description = "Role to tag in posts."]
     #[rename = "tagrole"] feed_role: Option<serenity::Role>,
 
-    #[description = "Specific user/cafe tag to pull posts from."]
-    #[rename = "tag"]
-    #[max_length = 30] heycafe_tag: Option<String>
+    #[description = "User/cafe tags to pull posts from, separated by commas."]
+    #[rename = "tags"]
+    #[autocomplete = "autocomplete_tag"]
+    #[max_length = 200] include_tags: Option<String>,
+
+    #[description = "User/cafe tags to skip, separated by commas."]
+    #[rename = "exclude"]
+    #[autocomplete = "autocomplete_tag"]
+    #[max_length = 200] exclude_tags: Option<String>,
+
+    #[description = "Which posts of a user to share, their own, ones in cafes or both."]
+    #[rename = "mode"] post_mode: Option<PostMode>,
+
+    #[description = "Seconds between checks for this feed, defaults to the server setting."]
+    #[rename = "interval"]
+    #[min = 30]
+    #[max = 86400] poll_interval: Option<i64>,
+
+    #[description = "Update the role, tags, mode and interval if this feed is already set up."]
+    update: Option<bool>,
+
+    #[description = "Post this many of the latest conversations right away."]
+    #[min = 1]
+    #[max = 10] backfill: Option<u32>
 ) -> Result<(), Error> {
+    // Looking up the source can take longer than Discord waits for a reply
+    ctx.defer().await?;
+
     // Analyze alias for type and grab data
-    let api_feed_type = match alias.chars().nth(0).unwrap() {
-        '!' => {
-            alias = alias.strip_prefix('!').unwrap().to_string();
-            "cafe_info"
-        },
-        _ => {
-            alias = alias.strip_prefix('@').unwrap_or(&alias).to_string();
-            "account_info"
-        }
-    };
-    
-    let heycafe_data = match grab_feed_data(format!("https://endpoint.hey.cafe/api/{api_feed_type}?query={alias}&convert_numeric=tags"), &ctx.data().client).await {
-        Ok(data) => data,
-        Err(err) => return Err(err)
-    };
+    let feed_source = FeedSource::parse(&alias, source_type)?;
+    let (feed_type, alias) = (feed_source.feed_type, feed_source.alias.as_str());
+
+    let source = sources::lookup(&ctx.data().client, feed_type, alias).await?;
+    let heycafe_id = source.id.clone();
+
+    // Keep names around so listing feeds doesn't need Hey.Cafe
+    sources::save(&ctx.data().database, feed_type, &source).await?;
 
     // Validate other args and get necessary info
-    let heycafe_id = heycafe_data["response_data"]["id"].as_str().unwrap();
     let guild_id = *ctx.guild_id().unwrap().as_u64() as i64;
     let feed_channel_id = *feed_channel.id().as_u64() as i64;
 
-    let feed_role_id = match feed_role {
-        Some(role) => *role.id.as_u64() as i64,
-        None => 0
-    };
+    // Fall back to the server's default mention role
+    let settings = GuildSettings::load(&ctx.data().database, guild_id).await?;
+    let chosen_role_id = feed_role.as_ref().map(|role| *role.id.as_u64() as i64);
+    let feed_role_id = chosen_role_id.unwrap_or(settings.default_mention_role_id);
 
-    let tag_id = match grab_tag_id(heycafe_tag.clone(), heycafe_data["response_data"]["tags"].as_array()) {
-        Ok(id) => id,
-        Err(err) => return Err(err)
+    let tag_filter = TagFilter {
+        include: grab_tag_ids(include_tags.as_deref(), &source.tags)?,
+        exclude: grab_tag_ids(exclude_tags.as_deref(), &source.tags)?,
     };
+    let include_tag_ids = feed::join_ids(&tag_filter.include);
+    let exclude_tag_ids = feed::join_ids(&tag_filter.exclude);
+    let tag_addon = describe_tags(&tag_filter, &source.tags);
+
+    if post_mode.is_some() && feed_type == FeedType::Cafe {
+        return Err(BotCafeError::InvalidInput(String::from("The mode option is only for user feeds, cafe feeds share every post!")));
+    }
 
-    let feed_type = match api_feed_type {
-        "account_info" => "user",
-        _ => "cafe"
+    // Check for an existing subscription before adding a new one
+    let existing = sqlx::query!(r#"SELECT id, mention_role_id, poll_interval_secs, include_tag_ids, exclude_tag_ids, post_mode AS "post_mode: PostMode" FROM heycafe_feeds WHERE guild_id = ? AND channel_id = ? AND feed_type = ? AND heycafe_id = ?"#, guild_id, feed_channel_id, feed_type, heycafe_id)
+        .fetch_optional(&ctx.data().database)
+        .await?;
+
+    if let Some(existing) = existing {
+        if update != Some(true) {
+            return Err(BotCafeError::InvalidInput(format!("This server is already subscribed to {alias} in {feed_channel}! Set the update option to change its role, tags or interval instead.")));
+        }
+
+        // Keep the current settings unless new ones were given
+        let feed_role_id = chosen_role_id.unwrap_or(existing.mention_role_id);
+        let poll_interval = poll_interval.or(existing.poll_interval_secs);
+        let include_tag_ids = if include_tags.is_some() { include_tag_ids } else { existing.include_tag_ids };
+        let exclude_tag_ids = if exclude_tags.is_some() { exclude_tag_ids } else { existing.exclude_tag_ids };
+        let post_mode = post_mode.unwrap_or(existing.post_mode);
+
+        sqlx::query!("UPDATE heycafe_feeds SET mention_role_id = ?, poll_interval_secs = ?, include_tag_ids = ?, exclude_tag_ids = ?, post_mode = ?, enabled = TRUE, consecutive_failures = 0, last_error = NULL, retry_after = 0 WHERE id = ?", feed_role_id, poll_interval, include_tag_ids, exclude_tag_ids, post_mode, existing.id)
+            .execute(&ctx.data().database)
+            .await?;
+
+        let msg = format!("Updated the feed for {alias} in the channel {feed_channel}!");
+        ctx.say(msg).await?;
+        return Ok(());
+    }
+
+    // Start from the newest conversation so old posts aren't shared, unless a backfill was asked for.
+    // User feeds and feeds limited by tags look further back to find enough conversations they share
+    let backfill = backfill.unwrap_or(0);
+    let post_mode = post_mode.unwrap_or_default();
+    let count = if tag_filter.is_empty() && feed_type == FeedType::Cafe { backfill.max(1) } else { BACKFILL_SEARCH };
+    let latest = grab_latest_conversations(&ctx.data().client, feed_type, &heycafe_id, count).await?;
+
+    let (last_post_id, last_post_timestamp) = match latest.first() {
+        Some(conversation) => (conversation.id.clone(), conversation.date_created),
+        None => (String::from("0"), 0)
     };
 
     // Insert into DB and send msg
-    sqlx::query!("INSERT INTO heycafe_feeds (guild_id, feed_type, channel_id, heycafe_id, last_post_id, mention_role_id, tag_id) VALUES (?, ?, ?, ?, 0, ?, ?)", guild_id, feed_type, feed_channel_id, heycafe_id, feed_role_id, tag_id)
+    let feed_id = sqlx::query!("INSERT INTO heycafe_feeds (guild_id, feed_type, channel_id, heycafe_id, last_post_id, mention_role_id, include_tag_ids, exclude_tag_ids, post_mode, poll_interval_secs, last_post_timestamp) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", guild_id, feed_type, feed_channel_id, heycafe_id, last_post_id, feed_role_id, include_tag_ids, exclude_tag_ids, post_mode, poll_interval, last_post_timestamp)
         .execute(&ctx.data().database)
         .await
-        .unwrap();
+        .map_err(|err| already_subscribed(err, alias))?
+        .last_insert_rowid();
+
+    let mut backfilled = 0;
+    if backfill > 0 {
+        let feed = sqlx::query_as!(UserFeed,
+            r#"SELECT id, guild_id, feed_type AS "feed_type: FeedType", channel_id, heycafe_id, last_post_id, mention_role_id, last_post_timestamp,
+            enabled, consecutive_failures, last_error, last_success_at, retry_after, poll_interval_secs, last_checked_at, include_tag_ids, exclude_tag_ids,
+            post_mode AS "post_mode: PostMode"
+            FROM heycafe_feeds WHERE id = ?"#, feed_id)
+            .fetch_one(&ctx.data().database)
+            .await?;
 
-    let tag_addon = if tag_id != "none" {
-        format!(" with the tag {}", heycafe_tag.unwrap())
+        let matching: Vec<&Conversation> = latest.iter()
+            .filter(|conversation| feed.shares(conversation.cafe.is_some()))
+            .filter(|conversation| tag_filter.allows(conversation.tag.as_ref().map(|tag| tag.id.as_str())))
+            .take(backfill as usize)
+            .collect();
+
+        // Oldest first, the same order the poller uses
+        for conversation in matching.into_iter().rev() {
+            match poller::post_conversation(ctx.serenity_context(), &feed, &settings, conversation).await {
+                Ok(()) => backfilled += 1,
+                Err(err) if poller::is_rejected_post(&err) => {
+                    println!("[FEED] Discord rejected a backfilled post, skipping it - Feed: {feed_id} - Post ID: {} - Error: {}", conversation.id, err);
+                },
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    let backfill_addon = if backfilled > 0 {
+        format!(" Shared the latest {backfilled} conversation(s) to get started.")
     } else {
         String::new()
     };
 
-    let msg = format!("Now listening to {alias} and posting in the channel {feed_channel}{tag_addon}!");
+    let msg = format!("Now listening to {alias} and posting in the channel {feed_channel}{tag_addon}!{backfill_addon}");
     ctx.say(msg).await?;
     Ok(())
 }
@@ -89,63 +181,51 @@ pub async fn add(
 /// Remove a feed that is being listened to!
 #[poise::command(
     slash_command,
-    required_permissions = "MANAGE_CHANNELS",
+    check = "crate::checks::can_manage_feeds",
 )]
 pub async fn remove(
     ctx: Context<'_>,
-    #[description = "Alias of the user or cafe."]
-    #[max_length = 30] mut alias: String,
+    #[description = "Alias or hey.cafe link of the user or cafe."]
+    #[autocomplete = "autocomplete_subscription"]
+    #[max_length = 100] alias: String,
 
-    #[description = "User/cafe tag being listened to."]
-    #[rename = "tag"]
-    #[max_length = 30] heycafe_tag: Option<String>
+    #[description = "Whether the alias is a user or a cafe, if it doesn't start with @ or !."]
+    #[rename = "source"] source_type: Option<FeedType>,
+
+    #[description = "Only remove the feed posting in this channel."]
+    #[rename = "channel"] feed_channel: Option<serenity::Channel>
 ) -> Result<(), Error> {
-    // Form URL and grab data
-    let api_feed_type = match alias.chars().nth(0).unwrap() {
-        '!' => {
-            alias = alias.strip_prefix('!').unwrap().to_string();
-            "cafe_info"
-        },
-        _ => {
-            alias = alias.strip_prefix('@').unwrap_or(&alias).to_string();
-            "account_info"
-        }
-    };
-    
-    let heycafe_data = match grab_feed_data(format!("https://endpoint.hey.cafe/api/{api_feed_type}?query={alias}&convert_numeric=tags"), &ctx.data().client).await {
-        Ok(data) => data,
-        Err(err) => return Err(err)
-    };
+    ctx.defer().await?;
 
-    let tag_id = match grab_tag_id(heycafe_tag.clone(), heycafe_data["response_data"]["tags"].as_array()) {
-        Ok(id) => id,
-        Err(err) => return Err(err)
-    };
+    // Analyze alias for type and grab data
+    let feed_source = FeedSource::parse(&alias, source_type)?;
+    let (feed_type, alias) = (feed_source.feed_type, feed_source.alias.as_str());
 
-    let heycafe_id = heycafe_data["response_data"]["id"].as_str().unwrap();
     let guild_id = *ctx.guild_id().unwrap().as_u64() as i64;
+    let source = sources::resolve(ctx.data(), guild_id, feed_type, alias).await?;
+    let heycafe_id = source.id.clone();
+
+    let feed_channel_id = feed_channel.as_ref().map(|channel| *channel.id().as_u64() as i64).unwrap_or(0);
 
     // Check database then run query if found
-    let db_check = sqlx::query!("SELECT COUNT(id) AS count FROM heycafe_feeds WHERE guild_id = ? AND heycafe_id = ? AND tag_id = ?", guild_id, heycafe_id, tag_id)
+    let db_check = sqlx::query!("SELECT COUNT(id) AS count FROM heycafe_feeds WHERE guild_id = ? AND feed_type = ? AND heycafe_id = ? AND (? = 0 OR channel_id = ?)", guild_id, feed_type, heycafe_id, feed_channel_id, feed_channel_id)
         .fetch_one(&ctx.data().database)
-        .await
-        .unwrap();
+        .await?;
 
     if db_check.count == 0 {
-        if heycafe_tag.is_some() {
-            return Err(format!("No feed was found in the database with the alias \"{alias}\" and tag \"{}\"!", heycafe_tag.unwrap()).into());
+        if let Some(feed_channel) = &feed_channel {
+            return Err(BotCafeError::InvalidInput(format!("No feed was found in the database with the alias \"{alias}\" in {feed_channel}!")));
         } else {
-            return Err(format!("No feed was found in the database with the alias \"{alias}\"!").into());
+            return Err(BotCafeError::InvalidInput(format!("No feed was found in the database with the alias \"{alias}\"!")));
         }
     }
 
-    sqlx::query!("DELETE FROM heycafe_feeds WHERE guild_id = ? AND heycafe_id = ? AND tag_id = ?", guild_id, heycafe_id, tag_id)
+    sqlx::query!("DELETE FROM heycafe_feeds WHERE guild_id = ? AND feed_type = ? AND heycafe_id = ? AND (? = 0 OR channel_id = ?)", guild_id, feed_type, heycafe_id, feed_channel_id, feed_channel_id)
         .execute(&ctx.data().database)
-        .await
-        .unwrap();
+        .await?;
 
-    let msg = if heycafe_tag.is_some() {
-        format!("No longer listening to {alias} with the tag {}!", heycafe_tag.unwrap())
+    let msg = if let Some(feed_channel) = feed_channel {
+        format!("No longer listening to {alias} in {feed_channel}!")
     } else {
         format!("No longer listening to {alias}!")
     };
@@ -154,24 +234,304 @@ pub async fn remove(
     Ok(())
 }
 
+/// Change the channel, role or tags of a feed.
+#[poise::command(
+    slash_command,
+    check = "crate::checks::can_manage_feeds",
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn edit(
+    ctx: Context<'_>,
+    #[description = "Alias or hey.cafe link of the user or cafe."]
+    #[autocomplete = "autocomplete_subscription"]
+    #[max_length = 100] alias: String,
+
+    #[description = "Whether the alias is a user or a cafe, if it doesn't start with @ or !."]
+    #[rename = "source"] source_type: Option<FeedType>,
+
+    #[description = "Channel the feed posts in, if there is more than one."]
+    #[rename = "channel"] feed_channel: Option<serenity::Channel>,
+
+    #[description = "New channel to post feeds."]
+    #[rename = "newchannel"] new_channel: Option<serenity::Channel>,
+
+    #[description = "New role to tag in posts."]
+    #[rename = "tagrole"] feed_role: Option<serenity::Role>,
+
+    #[description = "Stop tagging a role in posts."]
+    #[rename = "removerole"] remove_role: Option<bool>,
+
+    #[description = "User/cafe tags to pull posts from, separated by commas, or \"none\" for every tag."]
+    #[rename = "tags"]
+    #[autocomplete = "autocomplete_tag"]
+    #[max_length = 200] include_tags: Option<String>,
+
+    #[description = "User/cafe tags to skip, separated by commas, or \"none\" to skip nothing."]
+    #[rename = "exclude"]
+    #[autocomplete = "autocomplete_tag"]
+    #[max_length = 200] exclude_tags: Option<String>,
+
+    #[description = "Which posts of a user to share, their own, ones in cafes or both."]
+    #[rename = "mode"] post_mode: Option<PostMode>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    // Analyze alias for type and grab data
+    let feed_source = FeedSource::parse(&alias, source_type)?;
+    let alias = feed_source.alias.as_str();
+
+    let (source, feed_id) = find_feed(ctx, &feed_source, feed_channel.as_ref()).await?;
+    let feed = sqlx::query!(r#"SELECT id, feed_type AS "feed_type: FeedType", channel_id, mention_role_id, include_tag_ids, exclude_tag_ids, post_mode AS "post_mode: PostMode" FROM heycafe_feeds WHERE id = ?"#, feed_id)
+        .fetch_one(&ctx.data().database)
+        .await?;
+
+    // Work out the new values, keeping anything that wasn't given
+    let mut changes = Vec::new();
+
+    let channel_id = match &new_channel {
+        Some(channel) => {
+            changes.push(format!("now posting in {channel}"));
+            *channel.id().as_u64() as i64
+        },
+        None => feed.channel_id
+    };
+
+    let mention_role_id = match (&feed_role, remove_role) {
+        (Some(role), _) => {
+            changes.push(format!("now tagging {}", role.name));
+            *role.id.as_u64() as i64
+        },
+        (None, Some(true)) => {
+            changes.push(String::from("no longer tagging a role"));
+            0
+        },
+        _ => feed.mention_role_id
+    };
+
+    let include_tag_ids = match include_tags.as_deref() {
+        Some(include_tags) => {
+            let ids = grab_tag_ids(Some(include_tags), &source.tags)?;
+            if ids.is_empty() {
+                changes.push(String::from("now posting from every tag"));
+            } else {
+                changes.push(format!("now posting from {}", tag_names(&ids, &source.tags)));
+            }
+            feed::join_ids(&ids)
+        },
+        None => feed.include_tag_ids.clone()
+    };
+
+    let exclude_tag_ids = match exclude_tags.as_deref() {
+        Some(exclude_tags) => {
+            let ids = grab_tag_ids(Some(exclude_tags), &source.tags)?;
+            if ids.is_empty() {
+                changes.push(String::from("no longer skipping any tags"));
+            } else {
+                changes.push(format!("now skipping {}", tag_names(&ids, &source.tags)));
+            }
+            feed::join_ids(&ids)
+        },
+        None => feed.exclude_tag_ids.clone()
+    };
+
+    let post_mode = match post_mode {
+        Some(_) if feed.feed_type == FeedType::Cafe => {
+            return Err(BotCafeError::InvalidInput(String::from("The mode option is only for user feeds, cafe feeds share every post!")));
+        },
+        Some(post_mode) => {
+            changes.push(format!("now sharing {}", describe_mode(post_mode)));
+            post_mode
+        },
+        None => feed.post_mode
+    };
+
+    // Editing also re-enables a feed that was disabled after failing
+    sqlx::query!("UPDATE heycafe_feeds SET channel_id = ?, mention_role_id = ?, include_tag_ids = ?, exclude_tag_ids = ?, post_mode = ?, enabled = TRUE, consecutive_failures = 0, last_error = NULL, retry_after = 0 WHERE id = ?", channel_id, mention_role_id, include_tag_ids, exclude_tag_ids, post_mode, feed.id)
+        .execute(&ctx.data().database)
+        .await
+        .map_err(|err| already_subscribed(err, alias))?;
+
+    let msg = if changes.is_empty() {
+        format!("The feed for {alias} is up to date!")
+    } else {
+        format!("The feed for {alias} is {}!", changes.join(", "))
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
 // Important funcs
-// FUNCTION - Returns tag id from a given tag alias
-fn grab_tag_id(tag_alias: Option<String>, tag_data: Option<&Vec<Value>>) -> Result<String, Error> {
-    if tag_alias.is_none() {
-        return Ok(String::from("none"));
+// FUNCTION - Finds this server's feed for a user or cafe, using the channel when it posts in more than one
+pub(crate) async fn find_feed(ctx: Context<'_>, feed_source: &FeedSource, feed_channel: Option<&serenity::Channel>) -> Result<(SourceInfo, i64), Error> {
+    let guild_id = *ctx.guild_id().unwrap().as_u64() as i64;
+    let source = sources::resolve(ctx.data(), guild_id, feed_source.feed_type, &feed_source.alias).await?;
+
+    let feed_channel_id = feed_channel.map(|channel| *channel.id().as_u64() as i64).unwrap_or(0);
+
+    let feeds = sqlx::query!("SELECT id FROM heycafe_feeds WHERE guild_id = ? AND feed_type = ? AND heycafe_id = ? AND (? = 0 OR channel_id = ?)", guild_id, feed_source.feed_type, source.id, feed_channel_id, feed_channel_id)
+        .fetch_all(&ctx.data().database)
+        .await?;
+
+    let alias = &feed_source.alias;
+    match feeds.as_slice() {
+        [feed] => Ok((source, feed.id)),
+        [] => Err(BotCafeError::InvalidInput(format!("No feed was found in the database with the alias \"{alias}\"!"))),
+        _ => Err(BotCafeError::InvalidInput(format!("{alias} is posting in more than one channel, pick the feed with the channel option!")))
     }
+}
 
-    if tag_data.is_none() {
-        return Err("The cafe or user specified doesn't have tags!".into());
+// FUNCTION - Returns up to `count` of the newest conversations from a user or cafe, newest first
+async fn grab_latest_conversations(client: &HeyCafeClient, feed_type: FeedType, heycafe_id: &str, count: u32) -> Result<Vec<Conversation>, Error> {
+    let conversations = match feed_type {
+        FeedType::User => client.account_conversations(heycafe_id, None, count, 0).await?,
+        FeedType::Cafe => client.cafe_conversations(heycafe_id, None, count, 0).await?
+    };
+
+    Ok(conversations)
+}
+
+// FUNCTION - Turns a broken unique index into an "already subscribed" reply
+fn already_subscribed(err: sqlx::Error, alias: &str) -> BotCafeError {
+    match &err {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            BotCafeError::InvalidInput(format!("That channel is already subscribed to {alias}!"))
+        },
+        _ => BotCafeError::Database(err)
     }
+}
+
+// FUNCTION - Returns tag ids from a comma separated list of tag names, "none" meaning no tags
+fn grab_tag_ids(tag_names: Option<&str>, tag_data: &[Tag]) -> Result<Vec<String>, Error> {
+    let Some(tag_names) = tag_names.filter(|tag_names| !tag_names.trim().eq_ignore_ascii_case("none")) else {
+        return Ok(Vec::new());
+    };
 
-    let tag_alias = tag_alias.unwrap();
+    let mut tag_ids = Vec::new();
+    for tag_name in tag_names.split(',').map(str::trim).filter(|tag_name| !tag_name.is_empty()) {
+        if tag_data.is_empty() {
+            return Err(BotCafeError::InvalidInput(String::from("The cafe or user specified doesn't have tags!")));
+        }
 
-    for tag in tag_data.unwrap() {
-        if tag_alias == tag["name"].as_str().unwrap() {
-            return Ok(tag["id"].as_str().unwrap().to_string());
+        match tag_data.iter().find(|tag| tag.name.eq_ignore_ascii_case(tag_name)) {
+            Some(tag) if !tag_ids.contains(&tag.id) => tag_ids.push(tag.id.clone()),
+            Some(_) => {},
+            None => return Err(BotCafeError::InvalidInput(format!("The tag \"{tag_name}\" was not found!")))
         }
     }
 
-    Err(format!("The tag \"{tag_alias}\" was not found!").into())
-}
\ No newline at end of file
+    Ok(tag_ids)
+}
+
+// FUNCTION - Names of the given tag ids, for replies
+fn tag_names(tag_ids: &[String], tag_data: &[Tag]) -> String {
+    tag_ids.iter()
+        .filter_map(|tag_id| tag_data.iter().find(|tag| &tag.id == tag_id))
+        .map(|tag| tag.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// FUNCTION - Describes a post mode for replies
+fn describe_mode(post_mode: PostMode) -> &'static str {
+    match post_mode {
+        PostMode::Personal => "only posts on the user's profile",
+        PostMode::Cafe => "only posts the user makes in cafes",
+        PostMode::Both => "posts on the user's profile and in cafes"
+    }
+}
+
+// FUNCTION - Describes a tag filter for replies, like " with the tags art, news"
+fn describe_tags(tag_filter: &TagFilter, tag_data: &[Tag]) -> String {
+    let mut description = String::new();
+    if !tag_filter.include.is_empty() {
+        description.push_str(&format!(" with the tags {}", tag_names(&tag_filter.include, tag_data)));
+    }
+    if !tag_filter.exclude.is_empty() {
+        description.push_str(&format!(" skipping the tags {}", tag_names(&tag_filter.exclude, tag_data)));
+    }
+
+    description
+}
+
+// Autocomplete
+// Discord shows at most 25 suggestions
+const MAX_SUGGESTIONS: usize = 25;
+
+// FUNCTION - Suggests users and cafes this server is subscribed to
+async fn autocomplete_subscription(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+    let guild_id = *guild_id.as_u64() as i64;
+
+    let subscriptions = sqlx::query!(
+        r#"SELECT DISTINCT heycafe_feeds.feed_type AS "feed_type: FeedType", heycafe_sources.alias
+        FROM heycafe_feeds
+        JOIN heycafe_sources ON heycafe_sources.feed_type = heycafe_feeds.feed_type AND heycafe_sources.heycafe_id = heycafe_feeds.heycafe_id
+        WHERE heycafe_feeds.guild_id = ?
+        ORDER BY heycafe_sources.alias"#,
+        guild_id)
+        .fetch_all(&ctx.data().database)
+        .await;
+
+    let subscriptions = match subscriptions {
+        Ok(subscriptions) => subscriptions,
+        Err(err) => {
+            println!("[ERROR] AUTOCOMPLETE: Failed to load subscriptions - Guild: {guild_id} - Error: {err}");
+            return Vec::new();
+        }
+    };
+
+    let partial = partial.trim_start_matches(['@', '!']).to_lowercase();
+    subscriptions.into_iter()
+        .filter(|subscription| subscription.alias.to_lowercase().contains(&partial))
+        .map(|subscription| format!("{}{}", subscription.feed_type.prefix(), subscription.alias))
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+// FUNCTION - Suggests the tags of the user or cafe picked in the alias option, completing the last tag in a list
+async fn autocomplete_tag(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let poise::Context::Application(app_ctx) = ctx else {
+        return Vec::new();
+    };
+
+    let option = |name: &str| app_ctx.args.iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_ref());
+
+    // Choices arrive as their index in the list of choices
+    let source_type = option("source")
+        .and_then(|value| value.as_u64())
+        .and_then(|index| <FeedType as poise::SlashArgument>::choices().into_iter().nth(index as usize))
+        .and_then(|choice| choice.name.parse::<FeedType>().ok());
+
+    let Some(Ok(source)) = option("alias")
+        .and_then(|value| value.as_str())
+        .map(|alias| FeedSource::parse(alias, source_type)) else {
+        return Vec::new();
+    };
+
+    let tags = match sources::tag_names(ctx.data(), source.feed_type, &source.alias).await {
+        Ok(tags) => tags,
+        Err(err) => {
+            println!("[ERROR] AUTOCOMPLETE: Failed to load tags - Alias: {source} - Error: {err}");
+            return Vec::new();
+        }
+    };
+
+    // Keep the tags already typed before the last comma
+    let (typed, last) = match partial.rsplit_once(',') {
+        Some((typed, last)) => (format!("{typed}, "), last.trim()),
+        None => (String::new(), partial.trim())
+    };
+
+    let last = last.to_lowercase();
+    tags.into_iter()
+        .filter(|tag| tag.to_lowercase().contains(&last))
+        .map(|tag| format!("{typed}{tag}"))
+        .filter(|suggestion| suggestion.chars().count() <= 100)
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
@@ -0,0 +1,200 @@
+// Keyword and regex rules for the contents of conversations
+use regex::{Regex, RegexBuilder};
+use crate::BotCafeError;
+
+// Longest pattern a rule can have
+const MAX_PATTERN_LENGTH: usize = 200;
+
+// Largest compiled regex allowed, so one rule can't slow down every feed check
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// Whether a rule lets matching conversations through or keeps them out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum FilterAction {
+    #[name = "include"]
+    Include,
+    #[name = "exclude"]
+    Exclude,
+}
+
+/// How a rule's pattern is matched against a conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum FilterKind {
+    #[name = "keyword"]
+    Keyword,
+    #[name = "regex"]
+    Regex,
+}
+
+// A single compiled rule
+#[derive(Debug)]
+enum Matcher {
+    Keyword(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn new(kind: FilterKind, pattern: &str) -> Result<Matcher, BotCafeError> {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return Err(BotCafeError::InvalidInput(String::from("Filters need something to match!")));
+        }
+        if pattern.chars().count() > MAX_PATTERN_LENGTH {
+            return Err(BotCafeError::InvalidInput(format!("Filters can be at most {MAX_PATTERN_LENGTH} characters long!")));
+        }
+
+        match kind {
+            FilterKind::Keyword => Ok(Matcher::Keyword(pattern.to_lowercase())),
+            FilterKind::Regex => RegexBuilder::new(pattern)
+                .size_limit(MAX_REGEX_SIZE)
+                .build()
+                .map(Matcher::Regex)
+                .map_err(|err| BotCafeError::InvalidInput(format!("\"{pattern}\" isn't a valid regex: {err}")))
+        }
+    }
+
+    // Keywords ignore case, regexes can use (?i) for that
+    fn is_match(&self, contents: &str, lowercase: &str) -> bool {
+        match self {
+            Matcher::Keyword(keyword) => lowercase.contains(keyword.as_str()),
+            Matcher::Regex(regex) => regex.is_match(contents),
+        }
+    }
+}
+
+/// Checks that a rule can be used, returning the pattern to store.
+pub fn validate(kind: FilterKind, pattern: &str) -> Result<String, BotCafeError> {
+    Matcher::new(kind, pattern)?;
+    Ok(pattern.trim().to_string())
+}
+
+/// Every rule of a feed, compiled once per check.
+#[derive(Debug, Default)]
+pub struct FilterSet {
+    include: Vec<Matcher>,
+    exclude: Vec<Matcher>,
+}
+
+impl FilterSet {
+    /// Compiles a feed's rules, given as (action, kind, pattern).
+    pub fn new<'a>(rules: impl IntoIterator<Item = (FilterAction, FilterKind, &'a str)>) -> Result<FilterSet, BotCafeError> {
+        let mut set = FilterSet::default();
+        for (action, kind, pattern) in rules {
+            let matcher = Matcher::new(kind, pattern)?;
+            match action {
+                FilterAction::Include => set.include.push(matcher),
+                FilterAction::Exclude => set.exclude.push(matcher),
+            }
+        }
+
+        Ok(set)
+    }
+
+    /// Whether a conversation should be posted: it has to match an include rule, if there are any, and no exclude rules.
+    pub fn allows(&self, contents: &str) -> bool {
+        let lowercase = contents.to_lowercase();
+
+        (self.include.is_empty() || self.include.iter().any(|matcher| matcher.is_match(contents, &lowercase)))
+            && !self.exclude.iter().any(|matcher| matcher.is_match(contents, &lowercase))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(rules: &[(FilterAction, FilterKind, &str)]) -> FilterSet {
+        FilterSet::new(rules.iter().copied()).unwrap()
+    }
+
+    #[test]
+    fn empty_set_allows_everything() {
+        assert!(FilterSet::default().allows("anything at all"));
+        assert!(FilterSet::default().allows(""));
+    }
+
+    #[test]
+    fn keywords_ignore_case() {
+        let filters = set(&[(FilterAction::Include, FilterKind::Keyword, "  Rust  ")]);
+        assert!(filters.allows("I like RUST a lot"));
+        assert!(filters.allows("rustaceans"));
+        assert!(!filters.allows("I like crabs"));
+    }
+
+    #[test]
+    fn include_needs_any_match() {
+        let filters = set(&[
+            (FilterAction::Include, FilterKind::Keyword, "cats"),
+            (FilterAction::Include, FilterKind::Keyword, "dogs"),
+        ]);
+        assert!(filters.allows("I love dogs"));
+        assert!(filters.allows("Cats!"));
+        assert!(!filters.allows("I love birds"));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filters = set(&[
+            (FilterAction::Include, FilterKind::Keyword, "release"),
+            (FilterAction::Exclude, FilterKind::Keyword, "beta"),
+        ]);
+        assert!(filters.allows("New release is out"));
+        assert!(!filters.allows("New BETA release is out"));
+        assert!(!filters.allows("beta testers wanted"));
+    }
+
+    #[test]
+    fn regexes_are_case_sensitive_unless_asked() {
+        let filters = set(&[(FilterAction::Exclude, FilterKind::Regex, r"^Ad\b")]);
+        assert!(!filters.allows("Ad: buy now"));
+        assert!(filters.allows("ad: buy now"));
+        assert!(filters.allows("Adventure time"));
+
+        let filters = set(&[(FilterAction::Include, FilterKind::Regex, r"(?i)v\d+\.\d+")]);
+        assert!(filters.allows("Out now: V1.2"));
+        assert!(!filters.allows("Out now: version one"));
+    }
+
+    #[test]
+    fn validate_trims_patterns() {
+        assert_eq!(validate(FilterKind::Keyword, "  spoiler ").unwrap(), "spoiler");
+        assert_eq!(validate(FilterKind::Regex, r" \d+ ").unwrap(), r"\d+");
+    }
+
+    #[test]
+    fn rejects_empty_and_long_patterns() {
+        assert!(validate(FilterKind::Keyword, "").is_err());
+        assert!(validate(FilterKind::Keyword, "   ").is_err());
+        assert!(validate(FilterKind::Keyword, &"a".repeat(MAX_PATTERN_LENGTH)).is_ok());
+        assert!(validate(FilterKind::Keyword, &"a".repeat(MAX_PATTERN_LENGTH + 1)).is_err());
+        assert!(validate(FilterKind::Regex, &"a".repeat(MAX_PATTERN_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_regexes() {
+        assert!(validate(FilterKind::Regex, "(unclosed").is_err());
+        assert!(validate(FilterKind::Regex, "[z-a]").is_err());
+        assert!(validate(FilterKind::Regex, "a{2,1}").is_err());
+        // Keywords aren't regexes, so the same text is fine
+        assert!(validate(FilterKind::Keyword, "(unclosed").is_ok());
+    }
+
+    #[test]
+    fn rejects_regexes_over_the_size_limit() {
+        let pattern = r"(?:\w{100}){100}";
+        assert!(pattern.len() <= MAX_PATTERN_LENGTH);
+        assert!(validate(FilterKind::Regex, pattern).is_err());
+        assert!(validate(FilterKind::Regex, r"\w{10}").is_ok());
+    }
+
+    #[test]
+    fn set_fails_on_any_bad_rule() {
+        let rules = [
+            (FilterAction::Include, FilterKind::Keyword, "fine"),
+            (FilterAction::Exclude, FilterKind::Regex, "(broken"),
+        ];
+        assert!(FilterSet::new(rules.iter().copied()).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+// Keyword and regex filters for feeds
+use crate::{feeds, heycafe, Context, Error, GuildSettings};
+use poise::serenity_prelude as serenity;
+use botcafe::filter::{self, FilterAction, FilterKind};
+use botcafe::{BotCafeError, FeedSource, FeedType};
+
+// Most filters a single feed can have
+const MAX_FILTERS_PER_FEED: i32 = 20;
+
+// Filters shown on each /feed filter list page
+const FILTERS_PER_PAGE: usize = 10;
+
+// PARENT
+#[poise::command(
+    slash_command,
+    subcommands("add", "remove", "list")
+)]
+pub async fn filter(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Only post conversations that match, or don't match, a keyword or regex.
+#[poise::command(
+    slash_command,
+    check = "crate::checks::can_manage_feeds",
+)]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "Alias or hey.cafe link of the user or cafe."]
+    #[max_length = 100] alias: String,
+
+    #[description = "Post only matching conversations, or skip them."] action: FilterAction,
+
+    #[description = "Match a keyword anywhere in the text, or a regex."] kind: FilterKind,
+
+    #[description = "Keyword or regex to match."]
+    #[max_length = 200] pattern: String,
+
+    #[description = "Whether the alias is a user or a cafe, if it doesn't start with @ or !."]
+    #[rename = "source"] source_type: Option<FeedType>,
+
+    #[description = "Channel the feed posts in, if there is more than one."]
+    #[rename = "channel"] feed_channel: Option<serenity::Channel>
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let feed_source = FeedSource::parse(&alias, source_type)?;
+    let (_, feed_id) = feeds::find_feed(ctx, &feed_source, feed_channel.as_ref()).await?;
+    let pattern = filter::validate(kind, &pattern)?;
+
+    let existing = sqlx::query!("SELECT COUNT(id) AS count FROM feed_filters WHERE feed_id = ?", feed_id)
+        .fetch_one(&ctx.data().database)
+        .await?;
+
+    if existing.count >= MAX_FILTERS_PER_FEED {
+        return Err(BotCafeError::InvalidInput(format!("Feeds can have at most {MAX_FILTERS_PER_FEED} filters, remove one first!")));
+    }
+
+    sqlx::query!("INSERT INTO feed_filters (feed_id, action, kind, pattern) VALUES (?, ?, ?, ?)", feed_id, action, kind, pattern)
+        .execute(&ctx.data().database)
+        .await
+        .map_err(|err| match &err {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                BotCafeError::InvalidInput(format!("The feed for {feed_source} already has that filter!"))
+            },
+            _ => BotCafeError::Database(err)
+        })?;
+
+    let msg = match action {
+        FilterAction::Include => format!("The feed for {feed_source} will now post conversations matching the {kind} `{pattern}`!"),
+        FilterAction::Exclude => format!("The feed for {feed_source} will now skip conversations matching the {kind} `{pattern}`!")
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Remove a filter from a feed.
+#[poise::command(
+    slash_command,
+    check = "crate::checks::can_manage_feeds",
+)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Number of the filter, from /feed filter list."] id: i64
+) -> Result<(), Error> {
+    let guild_id = *ctx.guild_id().unwrap().as_u64() as i64;
+
+    let removed = sqlx::query!("DELETE FROM feed_filters WHERE id = ? AND feed_id IN (SELECT id FROM heycafe_feeds WHERE guild_id = ?)", id, guild_id)
+        .execute(&ctx.data().database)
+        .await?;
+
+    if removed.rows_affected() == 0 {
+        return Err(BotCafeError::InvalidInput(format!("No filter #{id} was found for this server!")));
+    }
+
+    let msg = format!("Removed filter #{id}!");
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// List the filters of a feed.
+#[poise::command(
+    slash_command,
+    check = "crate::checks::can_manage_feeds",
+)]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Alias or hey.cafe link of the user or cafe."]
+    #[max_length = 100] alias: String,
+
+    #[description = "Whether the alias is a user or a cafe, if it doesn't start with @ or !."]
+    #[rename = "source"] source_type: Option<FeedType>,
+
+    #[description = "Channel the feed posts in, if there is more than one."]
+    #[rename = "channel"] feed_channel: Option<serenity::Channel>
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let feed_source = FeedSource::parse(&alias, source_type)?;
+    let (_, feed_id) = feeds::find_feed(ctx, &feed_source, feed_channel.as_ref()).await?;
+
+    let rules = sqlx::query!(r#"SELECT id, action AS "action: FilterAction", kind AS "kind: FilterKind", pattern FROM feed_filters WHERE feed_id = ? ORDER BY id"#, feed_id)
+        .fetch_all(&ctx.data().database)
+        .await?;
+
+    if rules.is_empty() {
+        let msg = format!("The feed for {feed_source} has no filters and posts every conversation!");
+        ctx.say(msg).await?;
+        return Ok(());
+    }
+
+    let rule_lines: Vec<String> = rules.into_iter()
+        .map(|rule| {
+            let action = match rule.action {
+                FilterAction::Include => "Posting",
+                FilterAction::Exclude => "Skipping"
+            };
+            format!("- #{} - {action} conversations matching the {} {}", rule.id, rule.kind, inline_code(&rule.pattern))
+        })
+        .collect();
+
+    let pages: Vec<String> = rule_lines.chunks(FILTERS_PER_PAGE)
+        .map(|lines| lines.join("\n"))
+        .collect();
+    let guild_id = *ctx.guild_id().unwrap().as_u64() as i64;
+    let color = GuildSettings::load(&ctx.data().database, guild_id).await?.embed_color as u32;
+
+    heycafe::paginate(ctx, &format!("Filters for {feed_source}"), color, &pages).await?;
+
+    Ok(())
+}
+
+// FUNCTION - Shows a pattern as inline code, even when it has backticks in it
+fn inline_code(pattern: &str) -> String {
+    // A zero width space after each backtick keeps them from closing the code span
+    format!("`` {} ``", pattern.replace('`', "`\u{200B}"))
+}
@@ -1,34 +1,45 @@
 // Used for miscellaneous commands
 
-use crate::{UserFeed, Context, Error};
+use crate::{UserFeed, Context, Error, GuildSettings};
 use poise::serenity_prelude as serenity;
 use reqwest::{get, Client, header::USER_AGENT};
 use serde_json::Value;
 use serenity::{ChannelId, RoleId};
+use botcafe::{FeedType, PostMode, TagFilter};
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Feeds shown on each /listfeeds page
+const FEEDS_PER_PAGE: usize = 10;
+
+// How long the page buttons keep working after the last press
+const PAGE_TIMEOUT: Duration = Duration::from_secs(300);
 
 // COMMAND - /listfeeds
 /// Lists all feeds set for this server.
 #[poise::command(
     slash_command,
-    required_permissions = "MANAGE_CHANNELS",
+    check = "crate::checks::can_manage_feeds",
 )]
 pub async fn listfeeds(
     ctx: Context<'_>,
-    #[description = "Type \"user\" or \"cafe\" for the type of feeds to list."] feed_type: String
+    #[description = "Only list user or cafe feeds."] feed_type: Option<FeedType>,
+    #[description = "Only list feeds posting in this channel."] channel: Option<serenity::Channel>
 ) -> Result<(), Error> {
     let guild_id = *ctx.guild_id().unwrap().as_u64() as i64;
-
-    // Grab feeds
-    if feed_type.as_str() != "user" && feed_type.as_str() != "cafe" {
-        let msg = format!("{}, you can only choose \"cafe\" or \"user\" as the feed type!", ctx.author());
-        ctx.say(msg).await?;
-        return Ok(());
-    }
-
-    let server_feeds: Vec<UserFeed> = sqlx::query_as!(UserFeed, "SELECT * FROM heycafe_feeds WHERE guild_id = ? AND feed_type = ?", guild_id, feed_type)
+    let channel_id = channel.as_ref().map(|channel| *channel.id().as_u64() as i64).unwrap_or(0);
+
+    // Names come from the local cache, filled by /feed add and the poller
+    let server_feeds = sqlx::query!(
+        r#"SELECT heycafe_feeds.feed_type AS "feed_type: FeedType", heycafe_feeds.heycafe_id, heycafe_feeds.channel_id, heycafe_feeds.mention_role_id, heycafe_feeds.enabled,
+        heycafe_feeds.post_mode AS "post_mode: PostMode", heycafe_feeds.include_tag_ids, heycafe_feeds.exclude_tag_ids, heycafe_sources.alias AS "alias?", heycafe_sources.display_name AS "display_name?"
+        FROM heycafe_feeds
+        LEFT JOIN heycafe_sources ON heycafe_sources.feed_type = heycafe_feeds.feed_type AND heycafe_sources.heycafe_id = heycafe_feeds.heycafe_id
+        WHERE heycafe_feeds.guild_id = ? AND (? IS NULL OR heycafe_feeds.feed_type = ?) AND (? = 0 OR heycafe_feeds.channel_id = ?)
+        ORDER BY heycafe_feeds.feed_type DESC, heycafe_feeds.id"#,
+        guild_id, feed_type, feed_type, channel_id, channel_id)
         .fetch_all(&ctx.data().database)
-        .await
-        .unwrap();
+        .await?;
 
     if server_feeds.is_empty() {
         let msg = format!("{}, no feeds found for this server!", ctx.author());
@@ -36,62 +47,80 @@ pub async fn listfeeds(
         return Ok(());
     }
 
-    let ftype =  if feed_type.as_str() == "user" { "account" } else { "cafe" };
+    // Cached tag names for every source this server follows
+    let tag_rows = sqlx::query!(
+        r#"SELECT DISTINCT heycafe_tags.feed_type AS "feed_type: FeedType", heycafe_tags.heycafe_id, heycafe_tags.tag_id, heycafe_tags.name, heycafe_tags.emoji
+        FROM heycafe_tags
+        JOIN heycafe_feeds ON heycafe_feeds.feed_type = heycafe_tags.feed_type AND heycafe_feeds.heycafe_id = heycafe_tags.heycafe_id
+        WHERE heycafe_feeds.guild_id = ?"#,
+        guild_id)
+        .fetch_all(&ctx.data().database)
+        .await?;
+
+    let tag_names: HashMap<(FeedType, String, String), String> = tag_rows.into_iter()
+        .map(|tag| ((tag.feed_type, tag.heycafe_id, tag.tag_id), format!("{} {}", tag.emoji, tag.name)))
+        .collect();
 
     // Feeds to text
-    let mut feed_display = String::new();
+    let mut feed_lines = Vec::new();
     for feed in server_feeds {
-        let api_link = format!("https://endpoint.hey.cafe/api/{ftype}_info?query={}", feed.heycafe_id);
-
-        let api_info_request = ctx.data().client.get(api_link.clone())
-            .send()
-            .await;
-        
-        let api_info_request = match api_info_request {
-            Ok(ok) => ok,
-            Err(err) => {
-                if err.is_timeout() {
-                    println!("Timeout");
-                    break;
-                } else {
-                    println!("{err}");
-                    break;
-                }
-            }
+        let name = match (feed.display_name, feed.alias) {
+            (Some(display_name), Some(alias)) => format!("{display_name}({}{alias})", feed.feed_type.prefix()),
+            _ => format!("Not looked up yet ({} ID: {})", feed.feed_type, feed.heycafe_id)
         };
 
-        let api_info = api_info_request
-            .json::<serde_json::Value>()
-            .await?;
-
         let channel_id = ChannelId(feed.channel_id as u64);
 
-        let display_name = api_info["response_data"]["name"].as_str().unwrap();
-        let alias = api_info["response_data"]["alias"].as_str().unwrap();
-
-        let prefix = if feed_type.as_str() == "user" { "@" } else { "!" };
+        let tag_filter = TagFilter::from_columns(&feed.include_tag_ids, &feed.exclude_tag_ids);
+        let describe = |tag_ids: &[String]| tag_ids.iter()
+            .map(|tag_id| match tag_names.get(&(feed.feed_type, feed.heycafe_id.clone(), tag_id.clone())) {
+                Some(tag_name) => tag_name.clone(),
+                None => format!("Unknown (ID: {tag_id})")
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
 
-        let tag_name = if feed.tag_id != "none" {
-            format!("{} {}", api_info["response_data"]["tags"][&feed.tag_id]["emoji"].as_str().unwrap(), api_info["response_data"]["tags"][&feed.tag_id]["name"].as_str().unwrap())
-        } else {
-            String::from("None")
-        };
+        let mut tag_name = if tag_filter.include.is_empty() { String::from("All") } else { describe(&tag_filter.include) };
+        if !tag_filter.exclude.is_empty() {
+            tag_name = format!("{tag_name} - Skipping: {}", describe(&tag_filter.exclude));
+        }
 
         let role_name = if feed.mention_role_id != 0 {
             let role_id = RoleId(feed.mention_role_id as u64);
 
-            role_id.to_role_cached(ctx).unwrap().name
+            match role_id.to_role_cached(ctx) {
+                Some(role) => role.name,
+                None => String::from("Deleted role")
+            }
         } else {
             String::from("None")
         };
 
-        feed_display = format!("{feed_display}- Name: {display_name}({prefix}{alias}) - Channel: <#{channel_id}> - Tag: {tag_name} - Mentions: {role_name}\n");
+        // User feeds say which posts they share, unless it's only the user's own
+        let mode = match (feed.feed_type, feed.post_mode) {
+            (FeedType::User, PostMode::Cafe) => " - Posts: In cafes",
+            (FeedType::User, PostMode::Both) => " - Posts: Profile and cafes",
+            _ => ""
+        };
+
+        let status = if feed.enabled { "" } else { " - **Disabled**" };
+
+        feed_lines.push(format!("- Name: {name} - Channel: <#{channel_id}> - Tags: {tag_name} - Mentions: {role_name}{mode}{status}"));
     }
 
-    let title = if feed_type.as_str() == "user" { "__**User Feeds**__" } else { "__**Cafe Feeds**__" };
-    let msg = format!("{title}\n{feed_display}");
-    ctx.say(msg).await?;
-    println!("[LOG] COMMAND: /listfeeds {} - Guild {}({})", feed_type, ctx.guild().unwrap().name, guild_id);
+    let pages: Vec<String> = feed_lines.chunks(FEEDS_PER_PAGE)
+        .map(|lines| lines.join("\n"))
+        .collect();
+    let title = match feed_type {
+        Some(FeedType::User) => "User Feeds",
+        Some(FeedType::Cafe) => "Cafe Feeds",
+        None => "Feeds"
+    };
+    let color = GuildSettings::load(&ctx.data().database, guild_id).await?.embed_color as u32;
+
+    let type_text = feed_type.map(|feed_type| feed_type.to_string()).unwrap_or_else(|| String::from("all"));
+    println!("[LOG] COMMAND: /listfeeds {} - Guild {}({})", type_text, ctx.guild().unwrap().name, guild_id);
+    paginate(ctx, title, color, &pages).await?;
 
     Ok(())
 }
@@ -112,4 +141,72 @@ pub async fn hey(ctx: Context<'_>) -> Result<(), Error> {
     println!("[LOG] COMMAND: /hey - Guild: {}({})", ctx.guild().unwrap().name, guild_id);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// FUNCTION - Sends the pages as an embed with Previous/Next buttons
+pub(crate) async fn paginate(ctx: Context<'_>, title: &str, color: u32, pages: &[String]) -> Result<(), Error> {
+    let ctx_id = ctx.id();
+    let prev_button_id = format!("{ctx_id}prev");
+    let next_button_id = format!("{ctx_id}next");
+    let mut current_page = 0;
+
+    let reply = ctx.send(|m| {
+        m.embed(|e| page_embed(e, title, color, pages, current_page));
+        if pages.len() > 1 {
+            m.components(|c| page_buttons(c, &prev_button_id, &next_button_id, current_page, pages.len()));
+        }
+        m
+    }).await?;
+
+    if pages.len() <= 1 {
+        return Ok(());
+    }
+
+    // Only the member who ran the command can turn the pages
+    while let Some(press) = serenity::CollectComponentInteraction::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(PAGE_TIMEOUT)
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            current_page = (current_page + 1).min(pages.len() - 1);
+        } else if press.data.custom_id == prev_button_id {
+            current_page = current_page.saturating_sub(1);
+        } else {
+            continue;
+        }
+
+        press.create_interaction_response(ctx, |r| {
+            r.kind(serenity::InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|d| {
+                    d.embed(|e| page_embed(e, title, color, pages, current_page))
+                        .components(|c| page_buttons(c, &prev_button_id, &next_button_id, current_page, pages.len()))
+                })
+        }).await?;
+    }
+
+    // Remove the buttons once they stop working
+    reply.edit(ctx, |m| {
+        m.embed(|e| page_embed(e, title, color, pages, current_page))
+            .components(|c| c)
+    }).await?;
+
+    Ok(())
+}
+
+// FUNCTION - Builds the embed for one page
+fn page_embed<'a>(e: &'a mut serenity::CreateEmbed, title: &str, color: u32, pages: &[String], page: usize) -> &'a mut serenity::CreateEmbed {
+    e.title(title)
+        .color(color)
+        .description(&pages[page])
+        .footer(|f| f.text(format!("Page {} of {}", page + 1, pages.len())))
+}
+
+// FUNCTION - Builds the Previous/Next buttons, disabled at either end
+fn page_buttons<'a>(c: &'a mut serenity::CreateComponents, prev_id: &str, next_id: &str, page: usize, page_count: usize) -> &'a mut serenity::CreateComponents {
+    c.create_action_row(|row| {
+        row.create_button(|b| b.custom_id(prev_id).label("Previous").disabled(page == 0))
+            .create_button(|b| b.custom_id(next_id).label("Next").disabled(page + 1 >= page_count))
+    })
+}
@@ -1,5 +1,11 @@
-use serde_json::Value;
-type Error = Box<dyn std::error::Error + Send + Sync>;
+pub mod client;
+pub mod error;
+pub mod feed;
+pub mod filter;
+pub mod ratelimit;
+
+pub use error::BotCafeError;
+pub use feed::{FeedSource, FeedType, PostMode, TagFilter};
 
 // Decode HTML from Café feeds
 pub fn html_decode(mut content: String) -> String {
@@ -26,52 +32,3 @@ pub fn escpae_markdown(mut content: String) -> String {
     
     content
 }
-
-// FUNCTION - Returns API data from Hey.Cafe as a Result
-pub async fn grab_feed_data(url: String, client: &reqwest::Client) -> Result<Value, Error> {
-    let init_request = client.get(url.clone())
-        .send()
-        .await;
-
-    let init_request = match init_request {
-        Ok(ok) => ok,
-        Err(err) => {
-            if err.is_timeout() {
-                println!("Timeout");
-            } else {
-                println!("{}", err);
-            }
-
-            return Err("There was an error requesting information!".into());
-        }
-    };
-    
-    let heycafe_data = init_request
-        .json::<serde_json::Value>()
-        .await;
-
-    match heycafe_data {
-        Ok(data) => {
-            if data["system_api_error"].is_boolean() {
-                Ok(data)
-            } else {
-                println!("Feed not found - API Link: {}", url);
-                Err("No information was found!".into())
-            }
-        },
-        Err(e) => {
-            println!("JSON ERROR: {}", e);
-            Err("There was an error handling information!".into())
-        }
-    }
-}
-
-// Check API data for errors
-pub fn has_error(data: &Value) -> bool {
-    if !data["system_api_error"].is_boolean() {
-        println!("API ERROR - Error: {}", data["system_api_error"].as_str().unwrap());
-        return true;
-    }
-
-    false
-}
\ No newline at end of file
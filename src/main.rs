@@ -2,40 +2,87 @@
 use poise::serenity_prelude as serenity;
 use dotenv::dotenv;
 use tokio::time::Duration;
+use tokio::signal;
+use tokio::sync::{oneshot, watch};
 use reqwest::{get, Client, header::USER_AGENT};
 use serde_json::Value;
 use crate::serenity::{Mention, ChannelId, RoleId};
 use serenity::model::channel::Embed;
 use chrono::prelude::*;
-use botcafe::{html_decode, escpae_markdown, has_error, grab_feed_data};
+use botcafe::client::HeyCafeClient;
+use botcafe::ratelimit::TokenBucket;
+use botcafe::{BotCafeError, FeedType, PostMode, TagFilter};
 
-type Error = Box<dyn std::error::Error + Send + Sync>;
+type Error = BotCafeError;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
 mod heycafe;
 mod feeds;
+mod poller;
+mod checks;
+mod settings;
+mod sources;
+mod filters;
+mod database;
 
-async fn listener(ctx: &serenity::Context, event: &poise::Event<'_>, data: &Data) -> Result<(), Error> {
+async fn listener(_ctx: &serenity::Context, event: &poise::Event<'_>, data: &Data) -> Result<(), Error> {
     match event {
         poise::Event::GuildCreate { guild, .. } => {
             let guild_id = *guild.id.as_u64() as i64;
             let count = sqlx::query!("SELECT COUNT(id) AS count FROM guild_settings WHERE guild_id = ?", guild_id)
                 .fetch_one(&data.database)
-                .await
-                .unwrap();
+                .await?;
 
             if count.count == 0 {
                 sqlx::query!("INSERT INTO guild_settings (guild_id, feed_settings_required_roleid) VALUES (?, 0)", guild_id)
                     .execute(&data.database)
-                    .await
-                    .unwrap();
+                    .await?;
 
                 println!("[GUILD] Joined new guild: {} (ID: {})", guild.name, guild.id.as_u64());
             }
         },
+        poise::Event::GuildDelete { incomplete, .. } => {
+            // Unavailable guilds are outages, not removals
+            if incomplete.unavailable {
+                return Ok(());
+            }
+
+            let guild_id = *incomplete.id.as_u64() as i64;
+            let feeds = sqlx::query!("DELETE FROM heycafe_feeds WHERE guild_id = ?", guild_id)
+                .execute(&data.database)
+                .await?;
+            sqlx::query!("DELETE FROM guild_settings WHERE guild_id = ?", guild_id)
+                .execute(&data.database)
+                .await?;
+
+            println!("[GUILD] Left guild {} - removed {} feeds", guild_id, feeds.rows_affected());
+        },
+        poise::Event::ChannelDelete { channel } => {
+            remove_channel_feeds(data, *channel.guild_id.as_u64() as i64, *channel.id.as_u64() as i64).await?;
+        },
+        poise::Event::ThreadDelete { thread } => {
+            remove_channel_feeds(data, *thread.guild_id.as_u64() as i64, *thread.id.as_u64() as i64).await?;
+        },
+        poise::Event::GuildRoleDelete { guild_id, removed_role_id, .. } => {
+            let guild_id = *guild_id.as_u64() as i64;
+            let role_id = *removed_role_id.as_u64() as i64;
+
+            let feeds = sqlx::query!("UPDATE heycafe_feeds SET mention_role_id = 0 WHERE guild_id = ? AND mention_role_id = ?", guild_id, role_id)
+                .execute(&data.database)
+                .await?;
+            sqlx::query!("UPDATE guild_settings SET default_mention_role_id = 0 WHERE guild_id = ? AND default_mention_role_id = ?", guild_id, role_id)
+                .execute(&data.database)
+                .await?;
+            sqlx::query!("UPDATE guild_settings SET feed_settings_required_roleid = 0 WHERE guild_id = ? AND feed_settings_required_roleid = ?", guild_id, role_id)
+                .execute(&data.database)
+                .await?;
+
+            if feeds.rows_affected() > 0 {
+                println!("[GUILD] Role {} deleted in guild {} - cleared mentions on {} feeds", role_id, guild_id, feeds.rows_affected());
+            }
+        },
         poise::Event::Ready { .. } => {
             println!("Bot.Cafe started!");
-            feed_check(ctx, data).await?;
         },
         _ => {}
     }
@@ -43,160 +90,183 @@ async fn listener(ctx: &serenity::Context, event: &poise::Event<'_>, data: &Data
     Ok(())
 }
 
+// FUNCTION - Removes feeds posting to a deleted channel
+async fn remove_channel_feeds(data: &Data, guild_id: i64, channel_id: i64) -> Result<(), Error> {
+    let feeds = sqlx::query!("DELETE FROM heycafe_feeds WHERE guild_id = ? AND channel_id = ?", guild_id, channel_id)
+        .execute(&data.database)
+        .await?;
+    sqlx::query!("UPDATE guild_settings SET admin_channel_id = 0 WHERE guild_id = ? AND admin_channel_id = ?", guild_id, channel_id)
+        .execute(&data.database)
+        .await?;
+
+    if feeds.rows_affected() > 0 {
+        println!("[GUILD] Channel {} deleted in guild {} - removed {} feeds", channel_id, guild_id, feeds.rows_affected());
+    }
+
+    Ok(())
+}
+
+// Shortest poll interval a guild or feed can use
+const MIN_POLL_INTERVAL_SECS: i64 = 30;
+
 // Hey.Cafe Feed data
 #[derive(Debug)]
 #[allow(dead_code)]
 struct UserFeed {
     id: i64,
     guild_id: i64,
-    feed_type: String,
+    feed_type: FeedType,
     channel_id: i64,
     heycafe_id: String,
     last_post_id: String,
     mention_role_id: i64,
-    tag_id: String,
-    last_post_timestamp: i64
+    last_post_timestamp: i64,
+    enabled: bool,
+    consecutive_failures: i64,
+    last_error: Option<String>,
+    last_success_at: Option<i64>,
+    retry_after: i64,
+    poll_interval_secs: Option<i64>,
+    last_checked_at: i64,
+    include_tag_ids: String,
+    exclude_tag_ids: String,
+    post_mode: PostMode
 }
 
-// Hey.Cafe feeds
-async fn feed_check(ctx: &serenity::Context, data: &Data) -> Result<(), Error> {
-    loop {
-        println!("[{}] Running feed check...", Utc::now().format("%H:%M:%S"));
-        let feed_vector: Vec<UserFeed> = sqlx::query_as!(UserFeed, "SELECT * FROM heycafe_feeds")
-            .fetch_all(&data.database)
-            .await
-            .unwrap();
-
-        for feed in feed_vector {
-            // Grab data and run checks
-            let tag_var = if feed.tag_id.as_str() != "none" {
-                format!("&tag={}", feed.tag_id)
-            } else { String::new() };
-        
-            let api_feed_type = match feed.feed_type.as_str() {
-                "user" => "account_conversations",
-                "cafe" => "cafe_conversations",
-                _ => ""
-            };
-        
-            let api_feed_link = format!("https://endpoint.hey.cafe/api/{}?query={}&convert_numeric=conversations&count=1{}", api_feed_type, feed.heycafe_id, tag_var);
-
-            let api_data = match grab_feed_data(api_feed_link, &data.client).await {
-                Ok(data) => data,
-                Err(err) => {
-                    println!("{}", err);
-                    continue;
-                }
-            };
+impl UserFeed {
+    // Whether the feed shares a conversation, given if it was posted inside a cafe. Cafe feeds share everything
+    fn shares(&self, in_cafe: bool) -> bool {
+        self.feed_type == FeedType::Cafe || self.post_mode.allows(in_cafe)
+    }
 
-            if has_error(&api_data) { continue; }
+    // Tags this feed is limited to or skips
+    fn tag_filter(&self) -> TagFilter {
+        TagFilter::from_columns(&self.include_tag_ids, &self.exclude_tag_ids)
+    }
+}
 
-            if &feed.feed_type == "user" && !api_data["response_data"]["conversations"][0]["cafe"].is_boolean() { continue; }
+// Color used for feed embeds when a guild hasn't picked one
+const DEFAULT_EMBED_COLOR: i64 = 0x604fd8;
 
-            let new_timestamp = api_data["response_data"]["conversations"][0]["date_created"].as_str().unwrap().parse::<i64>().unwrap();
-            if feed.last_post_timestamp > new_timestamp  { continue; }
+// Guild settings, changed with /settings
+#[derive(Debug)]
+#[allow(dead_code)]
+struct GuildSettings {
+    id: i64,
+    guild_id: i64,
+    feed_settings_required_roleid: i64,
+    admin_channel_id: i64,
+    poll_interval_secs: Option<i64>,
+    default_mention_role_id: i64,
+    embed_color: i64,
+    timezone: String
+}
 
-            let new_id = api_data["response_data"]["conversations"][0]["id"].as_str().unwrap();
-            if new_id == feed.last_post_id { continue; }
+impl GuildSettings {
+    // Settings for a guild, or the defaults if it has none saved
+    async fn load(database: &sqlx::SqlitePool, guild_id: i64) -> Result<GuildSettings, Error> {
+        let settings = sqlx::query_as!(GuildSettings, "SELECT * FROM guild_settings WHERE guild_id = ?", guild_id)
+            .fetch_optional(database)
+            .await?;
 
-            // Format message to post
-            let channel_id: ChannelId = ChannelId(feed.channel_id as u64);
-            
-            let embed_author = match feed.feed_type.as_str() {
-                "user" => {
-                    format!("{} (@{})", 
-                        api_data["response_data"]["conversations"][0]["account"]["name"].as_str().unwrap(),
-                        api_data["response_data"]["conversations"][0]["account"]["alias"].as_str().unwrap())
-                },
-                "cafe" => {
-                    format!("{} (!{})",
-                        api_data["response_data"]["conversations"][0]["cafe"]["name"].as_str().unwrap(),
-                        api_data["response_data"]["conversations"][0]["cafe"]["alias"].as_str().unwrap())
-                },
-                _ => String::new()
-            };
+        Ok(settings.unwrap_or(GuildSettings {
+            id: 0,
+            guild_id,
+            feed_settings_required_roleid: 0,
+            admin_channel_id: 0,
+            poll_interval_secs: None,
+            default_mention_role_id: 0,
+            embed_color: DEFAULT_EMBED_COLOR,
+            timezone: String::from("UTC")
+        }))
+    }
 
-            let mut embed_desc = String::from(api_data["response_data"]["conversations"][0]["contents"].as_str().unwrap());
-            if embed_desc.chars().count() >= 4096 {
-                embed_desc = console::truncate_str(&embed_desc, 4096, "...").to_string();
-            }
-            embed_desc = html_decode(embed_desc);
-            embed_desc = escpae_markdown(embed_desc);
+    // Timezone for footer timestamps, falling back to UTC if the saved one is unknown
+    fn tz(&self) -> chrono_tz::Tz {
+        self.timezone.parse().unwrap_or(chrono_tz::UTC)
+    }
+}
 
-            let tag_info = if &feed.tag_id != "none" {
-                format!("{} {}", api_data["response_data"]["conversations"][0]["tag"]["emoji"].as_str().unwrap(), api_data["response_data"]["conversations"][0]["tag"]["name"].as_str().unwrap())
-            } else {
-                String::new()
-            };
+// Reply to failed commands based on what went wrong
+async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
+    match error {
+        poise::FrameworkError::Command { error, ctx } => {
+            println!("[ERROR] COMMAND: /{} - {}", ctx.command().qualified_name, error);
 
-            let mut attachment_info = String::new();
-            let mut image_url = String::new();
-            if !api_data["response_data"]["conversations"][0]["attachments"].is_boolean() {
-                attachment_info = String::from("Yes");
-
-                for (_key, value) in api_data["response_data"]["conversations"][0]["attachments"].as_object().unwrap().iter() {
-                    if value["type"].as_str().unwrap() == "image" {
-                        image_url = value["file"].as_str().unwrap().to_string();
-                        break;
-                    }
-                }
+            if let Err(err) = ctx.say(error_reply(&error)).await {
+                println!("[ERROR] Failed to send error message: {err}");
             }
-
-            let mention_text = if feed.mention_role_id != 0 {
-                format!("{}", Mention::from(RoleId(feed.mention_role_id as u64)))
-            } else {
-                String::new()
+        },
+        poise::FrameworkError::CommandCheckFailed { error, ctx } => {
+            let msg = match error {
+                Some(error) => {
+                    println!("[ERROR] CHECK: /{} - {}", ctx.command().qualified_name, error);
+                    error_reply(&error)
+                },
+                None => String::from("You need the Manage Channels permission or this server's feed manager role to do that!")
             };
 
-            // Post content
-            let send = channel_id.send_message(&ctx, |m| {
-                m.content(mention_text);
-                m.embed(|e| {
-                    e.color(0x604fd8);
-                    e.title(embed_author);
-                    e.url(format!("https://hey.cafe/conversation/{new_id}"));
-                    e.thumbnail(api_data["response_data"]["conversations"][0]["account"]["avatar"].as_str().unwrap());
-                    e.description(embed_desc);
-                    if !tag_info.is_empty() {
-                        e.field("Tag:", tag_info, true);
-                    }
-                    if !attachment_info.is_empty() {
-                        e.field("Attachments:", attachment_info, true);
-                    }
-                    if feed.feed_type == "cafe" {
-                        e.field("Author:", api_data["response_data"]["conversations"][0]["account"]["name"].as_str().unwrap(), true);
-                    }
-                    e.image(image_url);
-                    e.footer(|f|
-                        f.text(format!("Shared to Discord at {}", Utc::now().format("%Y-%m-%d %H:%M:%S")))
-                    )
-                })
-            }).await;
-
-            if let Err(e) = send {
-                println!("Failed to post message: {}", e);
-                continue;
+            if let Err(err) = ctx.send(|m| m.content(msg).ephemeral(true)).await {
+                println!("[ERROR] Failed to send error message: {err}");
+            }
+        },
+        other => {
+            if let Err(err) = poise::builtins::on_error(other).await {
+                println!("[ERROR] Failed to handle framework error: {err}");
             }
-
-            sqlx::query!("UPDATE heycafe_feeds SET last_post_id = ?, last_post_timestamp = ? WHERE channel_id = ? AND heycafe_id = ? AND tag_id = ?", new_id, new_timestamp, feed.channel_id, feed.heycafe_id, feed.tag_id)
-                .execute(&data.database)
-                .await
-                .unwrap();
-            
-            println!("NEW POST - Guild: {} - Channel: {} - Post ID: {}", feed.guild_id, feed.channel_id, new_id);
         }
-
-        tokio::time::sleep(Duration::from_secs(30)).await;
     }
+}
 
-    #[allow(unreachable_code)]
-    Ok(())
+// User-facing message for each kind of error
+fn error_reply(error: &BotCafeError) -> String {
+    match error {
+        BotCafeError::Network(_) => String::from("There was an error requesting information from Hey.Cafe! Please try again later."),
+        BotCafeError::Timeout => String::from("Hey.Cafe took too long to respond! Please try again later."),
+        BotCafeError::Unavailable(_) => String::from("Hey.Cafe is unavailable right now! Please try again later."),
+        BotCafeError::Decode(_) => String::from("There was an error handling information from Hey.Cafe!"),
+        BotCafeError::ApiError(message) => format!("Hey.Cafe returned an error: {message}"),
+        BotCafeError::NotFound => String::from("No user or cafe was found with that alias!"),
+        BotCafeError::Database(_) => String::from("There was an error accessing the database! Please try again later."),
+        BotCafeError::Discord(_) => String::from("There was an error talking to Discord!"),
+        BotCafeError::InvalidInput(message) => message.clone(),
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Data { // User data, which is stored and accessible in all command invocations
     database: sqlx::SqlitePool,
-    client: reqwest::Client
+    client: HeyCafeClient,
+    max_posts_per_cycle: usize,
+    poll_workers: usize,
+    max_feed_failures: i64,
+    max_backoff_secs: u64,
+    default_poll_interval_secs: i64
+}
+
+// Resolves once the process is asked to stop
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = terminate.recv() => {},
+            _ = signal::ctrl_c() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = signal::ctrl_c().await;
+    }
+}
+
+// Read a setting from the environment, falling back to a default
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<T>().ok())
+        .unwrap_or(default)
 }
 
 #[tokio::main]
@@ -213,14 +283,53 @@ async fn main() {
                 .create_if_missing(true),
         )
         .await.unwrap();
-    //sqlx::migrate!("./migrations").run(&database).await.unwrap();
+
+    // Databases from before migrations were tracked already have some of them
+    let migrator = sqlx::migrate!("./migrations");
+    if let Err(err) = database::adopt_existing_schema(&database, &migrator).await {
+        println!("[ERROR] Couldn't check the database for an existing schema - Error: {err}");
+        std::process::exit(1);
+    }
+    if let Err(err) = migrator.run(&database).await {
+        println!("[ERROR] Database migrations failed - Error: {err}");
+        println!("[ERROR] If this database was set up by hand, compare it with the files in migrations/ and the rows in its _sqlx_migrations table");
+        std::process::exit(1);
+    }
+
+    // Deploy pipelines can apply migrations without starting the bot
+    if std::env::args().any(|arg| arg == "--migrate-only") {
+        println!("Migrations applied!");
+        return;
+    }
 
     // Bulid Client
-    let client = Client::builder()
-        .timeout(Duration::from_secs(30))
-        .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko)")
-        .build()
-        .unwrap();
+    let requests_per_sec = env_or("HEYCAFE_REQUESTS_PER_SEC", 5.0_f64);
+    let client = HeyCafeClient::new(
+        Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko)")
+            .build()
+            .unwrap()
+    ).with_rate_limit(TokenBucket::new(env_or("HEYCAFE_REQUEST_BURST", 10), requests_per_sec));
+
+    // Limit on how many conversations a single feed can post per check
+    let max_posts_per_cycle = env_or("MAX_POSTS_PER_CYCLE", 5_usize).max(1);
+
+    // Number of feeds polled at the same time
+    let poll_workers = env_or("POLL_WORKERS", 8_usize).max(1);
+
+    // Failed checks in a row before a broken feed is disabled
+    let max_feed_failures = env_or("MAX_FEED_FAILURES", 10_i64).max(1);
+
+    // Longest a failing feed waits between retries
+    let max_backoff_secs = env_or("MAX_BACKOFF_SECS", 3600_u64).max(1);
+
+    // Seconds between checks for feeds and guilds without their own interval
+    let default_poll_interval_secs = env_or("POLL_INTERVAL_SECS", 30_i64).max(MIN_POLL_INTERVAL_SECS);
+
+    // Poller shutdown, requested on SIGTERM or Ctrl+C
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let (poller_tx, mut poller_rx) = oneshot::channel();
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
@@ -228,21 +337,49 @@ async fn main() {
                 heycafe::listfeeds(),
                 heycafe::hey(),
                 feeds::feed(),
+                settings::settings(),
             ],
             event_handler: |ctx, event, _, data| Box::pin(listener(ctx, event, data)),
+            on_error: |error| Box::pin(on_error(error)),
             ..Default::default()
         })
         .token(std::env::var("DISCORD_TOKEN").expect("missing DISCORD_TOKEN"))
         .intents(serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::GUILDS)
-        .setup(|ctx, _ready, framework| {
+        .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                Ok(Data {
+                let data = Data {
                     database,
                     client,
-                })
+                    max_posts_per_cycle,
+                    poll_workers,
+                    max_feed_failures,
+                    max_backoff_secs,
+                    default_poll_interval_secs,
+                };
+
+                // Setup only runs on the first Ready, so reconnects never start a second poller
+                let _ = poller_tx.send(poller::spawn(ctx.clone(), data.clone(), shutdown_rx));
+                Ok(data)
             })
-        });
+        })
+        .build()
+        .await
+        .unwrap();
+
+    // Stop polling, then disconnect from Discord
+    let shard_manager = framework.shard_manager().clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        println!("Shutting down...");
+
+        let _ = shutdown_tx.send(true);
+        if let Ok(poller) = poller_rx.try_recv() {
+            let _ = poller.await;
+        }
+
+        shard_manager.lock().await.shutdown_all().await;
+    });
 
-    framework.run().await.unwrap();
+    framework.start().await.unwrap();
 }
@@ -0,0 +1,783 @@
+// Polls Hey.Cafe for new conversations and posts them to Discord
+use crate::{sources, Data, Error, GuildSettings, UserFeed};
+use poise::serenity_prelude as serenity;
+use serenity::{ChannelId, Mention, RoleId};
+use chrono::prelude::*;
+use futures::FutureExt;
+use futures::stream::{self, StreamExt};
+use std::panic::AssertUnwindSafe;
+use rand::Rng;
+use std::collections::HashMap;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use botcafe::{html_decode, escpae_markdown, BotCafeError, FeedType, PostMode};
+use botcafe::client::Conversation;
+use botcafe::filter::{FilterAction, FilterKind, FilterSet};
+
+// Conversations requested per page while catching up on a feed
+const PAGE_SIZE: u32 = 10;
+
+// Pages a single check reads before giving up on catching every feed up, older conversations are then skipped
+const MAX_PAGES: u32 = 25;
+
+// Totals for a single feed check
+#[derive(Default)]
+struct CheckStats {
+    posted: usize,
+    failed: usize,
+}
+
+// How often the scheduler looks for feeds that are due
+const SCHEDULER_TICK: Duration = Duration::from_secs(10);
+
+// Starting delay for feeds backing off after a failure
+const BACKOFF_BASE: Duration = Duration::from_secs(30);
+
+// Delay before restarting a poller that stopped unexpectedly
+const RESTART_DELAY: Duration = Duration::from_secs(10);
+
+// Starts the poller in the background, restarting it whenever it fails
+pub fn spawn(ctx: serenity::Context, data: Data, shutdown: watch::Receiver<bool>) -> JoinHandle<()> {
+    tokio::spawn(supervise(ctx, data, shutdown))
+}
+
+// Keeps a single poller running until shutdown is requested
+async fn supervise(ctx: serenity::Context, data: Data, mut shutdown: watch::Receiver<bool>) {
+    loop {
+        let poller = tokio::spawn({
+            let ctx = ctx.clone();
+            let data = data.clone();
+            let shutdown = shutdown.clone();
+            async move { feed_check(&ctx, &data, shutdown).await }
+        });
+
+        match poller.await {
+            Ok(Ok(())) => {
+                println!("[POLLER] Stopped");
+                return;
+            },
+            Ok(Err(err)) => println!("[POLLER] Poller failed, restarting in {}s - Error: {}", RESTART_DELAY.as_secs(), err),
+            Err(err) if err.is_panic() => println!("[POLLER] Poller panicked, restarting in {}s", RESTART_DELAY.as_secs()),
+            Err(err) => println!("[POLLER] Poller was cancelled, restarting in {}s - Error: {}", RESTART_DELAY.as_secs(), err),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(RESTART_DELAY) => {},
+            _ = shutdown.changed() => {
+                println!("[POLLER] Stopped");
+                return;
+            }
+        }
+    }
+}
+
+// Hey.Cafe feeds - runs until shutdown is requested
+async fn feed_check(ctx: &serenity::Context, data: &Data, mut shutdown: watch::Receiver<bool>) -> Result<(), Error> {
+    while !*shutdown.borrow() {
+        // Feeds whose own interval, guild interval or the default interval has passed
+        let now = Utc::now().timestamp();
+        let feed_vector: Vec<UserFeed> = sqlx::query_as!(UserFeed,
+            r#"SELECT heycafe_feeds.id, heycafe_feeds.guild_id, heycafe_feeds.feed_type AS "feed_type: FeedType", heycafe_feeds.channel_id, heycafe_feeds.heycafe_id,
+            heycafe_feeds.last_post_id, heycafe_feeds.mention_role_id, heycafe_feeds.last_post_timestamp, heycafe_feeds.enabled,
+            heycafe_feeds.consecutive_failures, heycafe_feeds.last_error, heycafe_feeds.last_success_at, heycafe_feeds.retry_after,
+            heycafe_feeds.poll_interval_secs, heycafe_feeds.last_checked_at, heycafe_feeds.include_tag_ids, heycafe_feeds.exclude_tag_ids,
+            heycafe_feeds.post_mode AS "post_mode: PostMode"
+            FROM heycafe_feeds
+            LEFT JOIN guild_settings ON guild_settings.guild_id = heycafe_feeds.guild_id
+            WHERE heycafe_feeds.enabled = TRUE AND heycafe_feeds.retry_after <= ?
+            AND heycafe_feeds.last_checked_at + COALESCE(heycafe_feeds.poll_interval_secs, guild_settings.poll_interval_secs, ?) <= ?"#,
+            now, data.default_poll_interval_secs, now)
+            .fetch_all(&data.database)
+            .await?;
+
+        if feed_vector.is_empty() {
+            tokio::select! {
+                _ = tokio::time::sleep(SCHEDULER_TICK) => {},
+                _ = shutdown.changed() => {}
+            }
+            continue;
+        }
+
+        println!("[{}] Running feed check...", Utc::now().format("%H:%M:%S"));
+        let started = Instant::now();
+        let feed_count = feed_vector.len();
+
+        // Fetch each user/cafe once, no matter how many channels or tags follow it
+        let mut sources: HashMap<(FeedType, String), Vec<UserFeed>> = HashMap::new();
+        for feed in feed_vector {
+            let key = (feed.feed_type, feed.heycafe_id.clone());
+            sources.entry(key).or_default().push(feed);
+        }
+        let source_count = sources.len();
+
+        let results: Vec<CheckStats> = stream::iter(sources.into_values())
+            .map(|feeds| poll_source(ctx, data, feeds, &shutdown))
+            .buffer_unordered(data.poll_workers)
+            .collect()
+            .await;
+
+        let posted: usize = results.iter().map(|stats| stats.posted).sum();
+        let failed: usize = results.iter().map(|stats| stats.failed).sum();
+        println!("[{}] Feed check finished - Feeds: {feed_count} - Sources: {source_count} - Posts: {posted} - Failed: {failed} - Workers: {} - Took: {:.2?}",
+            Utc::now().format("%H:%M:%S"), data.poll_workers, started.elapsed());
+
+        tokio::select! {
+            _ = tokio::time::sleep(SCHEDULER_TICK) => {},
+            _ = shutdown.changed() => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Fetches one source and posts its new conversations to every feed following it
+async fn poll_source(ctx: &serenity::Context, data: &Data, feeds: Vec<UserFeed>, shutdown: &watch::Receiver<bool>) -> CheckStats {
+    let mut stats = CheckStats::default();
+
+    // Once shutdown is requested no new work starts, feeds left unchecked are due again on the next start
+    if *shutdown.borrow() {
+        return stats;
+    }
+
+    // Filters are needed up front to know how far back each feed has to look
+    let mut followers = Vec::new();
+    for feed in feeds {
+        match load_filters(data, &feed).await {
+            Ok(filters) => followers.push((feed, filters)),
+            Err(err) => {
+                log_feed_error(&feed, &err);
+                record_failure(ctx, data, &feed, &err.to_string(), !err.is_temporary()).await;
+                stats.failed += 1;
+            }
+        }
+    }
+    let feeds: Vec<&UserFeed> = followers.iter().map(|(feed, _)| feed).collect();
+
+    // Panics are caught here so one bad source or post only skips the feeds involved
+    // Fetching writes nothing, so it's dropped right away on shutdown
+    let mut stopping = shutdown.clone();
+    let fetched = tokio::select! {
+        fetched = AssertUnwindSafe(fetch_source_conversations(data, &followers)).catch_unwind() => fetched,
+        _ = stopping.wait_for(|stop| *stop) => return stats
+    };
+    let (conversations, examined) = match fetched {
+        Ok(Ok(fetched)) => fetched,
+        Ok(Err(err)) => {
+            for feed in &feeds {
+                log_feed_error(feed, &err);
+                record_failure(ctx, data, feed, &err.to_string(), !err.is_temporary()).await;
+            }
+            stats.failed += feeds.len();
+            return stats;
+        },
+        Err(_) => {
+            for feed in &feeds {
+                log_feed_panic(feed, "fetching conversations");
+                record_failure(ctx, data, feed, "Internal error while fetching conversations", true).await;
+            }
+            stats.failed += feeds.len();
+            return stats;
+        }
+    };
+
+    if let Some(source) = feeds.first() {
+        sources::refresh_if_stale(data, source.feed_type, &source.heycafe_id).await;
+    }
+
+    for ((feed, filters), examined) in followers.iter().zip(examined) {
+        if *shutdown.borrow() { break; }
+
+        if !examined && feed.last_post_id != "0" {
+            println!("[FEED] Feed {} didn't catch up within {} conversations, skipping older ones", feed.id, conversations.len());
+        }
+
+        let result = AssertUnwindSafe(post_new_conversations(ctx, data, feed, filters, &conversations)).catch_unwind().await;
+        match result {
+            Ok(Ok(posted)) => {
+                record_success(data, feed).await;
+                stats.posted += posted;
+            },
+            Ok(Err(err)) => {
+                log_feed_error(feed, &err);
+                record_failure(ctx, data, feed, &err.to_string(), !err.is_temporary()).await;
+                stats.failed += 1;
+            },
+            Err(_) => {
+                log_feed_panic(feed, "posting conversations");
+                record_failure(ctx, data, feed, "Internal error while posting conversations", true).await;
+                stats.failed += 1;
+            }
+        }
+    }
+
+    stats
+}
+
+// Clear a feed's failure count after a successful check
+async fn record_success(data: &Data, feed: &UserFeed) {
+    let now = Utc::now().timestamp();
+    let update = sqlx::query!("UPDATE heycafe_feeds SET consecutive_failures = 0, last_error = NULL, last_success_at = ?, last_checked_at = ?, retry_after = 0 WHERE id = ?", now, now, feed.id)
+        .execute(&data.database)
+        .await;
+
+    if let Err(err) = update {
+        println!("[FEED] Failed to record success - Feed: {} - Error: {}", feed.id, err);
+    }
+}
+
+// Track a failed check, disabling the feed once it keeps failing for good
+async fn record_failure(ctx: &serenity::Context, data: &Data, feed: &UserFeed, error: &str, permanent: bool) {
+    let failures = feed.consecutive_failures + 1;
+    let disable = permanent && failures >= data.max_feed_failures;
+    let enabled = !disable;
+    let now = Utc::now().timestamp();
+    let retry_after = now + backoff_delay(failures, data.max_backoff_secs).as_secs() as i64;
+
+    let update = sqlx::query!("UPDATE heycafe_feeds SET consecutive_failures = ?, last_error = ?, enabled = ?, last_checked_at = ?, retry_after = ? WHERE id = ?", failures, error, enabled, now, retry_after, feed.id)
+        .execute(&data.database)
+        .await;
+
+    if let Err(err) = update {
+        println!("[FEED] Failed to record failure - Feed: {} - Error: {}", feed.id, err);
+        return;
+    }
+
+    if disable {
+        println!("[FEED] DISABLED - Feed: {} - Guild: {} - Failures: {failures}", feed.id, feed.guild_id);
+        notify_disabled(ctx, data, feed, failures, error).await;
+    }
+}
+
+// Exponential backoff with jitter, so failing feeds don't all retry at once
+fn backoff_delay(failures: i64, max_secs: u64) -> Duration {
+    let exponent = failures.clamp(1, 16) as u32 - 1;
+    let delay = BACKOFF_BASE.as_secs().saturating_mul(1 << exponent).min(max_secs);
+
+    // Randomly shorten the delay by up to a quarter
+    let jitter = rand::thread_rng().gen_range(0..=delay / 4);
+    Duration::from_secs((delay - jitter).max(1))
+}
+
+// Let the guild know a feed was disabled, in its admin channel if one is set
+async fn notify_disabled(ctx: &serenity::Context, data: &Data, feed: &UserFeed, failures: i64, error: &str) {
+    let channel_id = match GuildSettings::load(&data.database, feed.guild_id).await {
+        Ok(settings) if settings.admin_channel_id != 0 => settings.admin_channel_id,
+        _ => feed.channel_id
+    };
+
+    let msg = format!("The Hey.Cafe {} feed posting in <#{}> was disabled after {failures} failed checks.\nLast error: {error}\nUse `/feed edit` to turn it back on once the problem is fixed.", feed.feed_type, feed.channel_id);
+    if let Err(err) = ChannelId(channel_id as u64).say(ctx, msg).await {
+        println!("[FEED] Failed to send disabled notice - Feed: {} - Guild: {} - Error: {}", feed.id, feed.guild_id, err);
+    }
+}
+
+// Posts the conversations a single feed hasn't seen yet and returns how many were posted
+async fn post_new_conversations(ctx: &serenity::Context, data: &Data, feed: &UserFeed, filters: &FilterSet, conversations: &[Conversation]) -> Result<usize, BotCafeError> {
+    let selection = new_conversations_for(feed, filters, conversations, data.max_posts_per_cycle);
+
+    // The newest conversation the feed hasn't seen, even if its tags keep it from being posted
+    let newest = conversations.first().filter(|conversation| !is_caught_up(feed, conversation));
+    if selection.posts.is_empty() {
+        if let Some(newest) = newest {
+            save_progress(data, feed, newest).await?;
+        }
+        return Ok(0);
+    }
+
+    let settings = GuildSettings::load(&data.database, feed.guild_id).await?;
+
+    // Post oldest first, saving progress after each post
+    let mut posted = 0;
+    for conversation in &selection.posts {
+        match post_conversation(ctx, feed, &settings, conversation).await {
+            Ok(()) => {
+                println!("NEW POST - Guild: {} - Channel: {} - Post ID: {}", feed.guild_id, feed.channel_id, conversation.id);
+                posted += 1;
+            },
+            // A post Discord won't take would otherwise block the feed until it's disabled
+            Err(err) if is_rejected_post(&err) => {
+                println!("[FEED] Discord rejected a post, skipping it - Feed: {} - Guild: {} - Post ID: {} - Error: {}", feed.id, feed.guild_id, conversation.id, err);
+            },
+            Err(err) => return Err(err),
+        }
+        save_progress(data, feed, conversation).await?;
+    }
+
+    // Skip past newer conversations that were filtered out, unless some are still waiting for the next check
+    if !selection.backlog {
+        if let Some(newest) = newest {
+            save_progress(data, feed, newest).await?;
+        }
+    }
+
+    Ok(posted)
+}
+
+// Whether Discord refused a single post, rather than the feed's channel being unusable
+pub(crate) fn is_rejected_post(err: &BotCafeError) -> bool {
+    match err {
+        BotCafeError::Discord(err) => match err.as_ref() {
+            serenity::Error::Http(err) => err.status_code().map(|status| status.as_u16()) == Some(400),
+            serenity::Error::Model(_) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Marks a conversation as the newest one the feed has seen
+async fn save_progress(data: &Data, feed: &UserFeed, conversation: &Conversation) -> Result<(), BotCafeError> {
+    sqlx::query!("UPDATE heycafe_feeds SET last_post_id = ?, last_post_timestamp = ? WHERE id = ?", conversation.id, conversation.date_created, feed.id)
+        .execute(&data.database)
+        .await?;
+
+    Ok(())
+}
+
+// Compiles the keyword and regex filters of a feed
+async fn load_filters(data: &Data, feed: &UserFeed) -> Result<FilterSet, BotCafeError> {
+    let rules = sqlx::query!(r#"SELECT action AS "action: FilterAction", kind AS "kind: FilterKind", pattern FROM feed_filters WHERE feed_id = ?"#, feed.id)
+        .fetch_all(&data.database)
+        .await?;
+
+    FilterSet::new(rules.iter().map(|rule| (rule.action, rule.kind, rule.pattern.as_str())))
+}
+
+// Conversations a feed posts in one check
+struct Selection<'a> {
+    // Oldest first
+    posts: Vec<&'a Conversation>,
+    // Whether more new conversations are left for the next check
+    backlog: bool,
+}
+
+// Picks the oldest conversations newer than the feed's last post, up to the per check limit
+fn new_conversations_for<'a>(feed: &UserFeed, filters: &FilterSet, conversations: &'a [Conversation], max_posts: usize) -> Selection<'a> {
+    let unseen: Vec<&Conversation> = conversations.iter()
+        .take_while(|conversation| !is_caught_up(feed, conversation))
+        .filter(|conversation| wants(feed, filters, conversation))
+        .collect();
+
+    // Feeds that have never posted only pick up the newest conversation
+    if feed.last_post_id == "0" {
+        return Selection { posts: unseen.into_iter().take(1).collect(), backlog: false };
+    }
+
+    let backlog = unseen.len() > max_posts;
+    if backlog {
+        println!("[FEED] Feed {} has {} new conversations, posting the oldest {max_posts} now and the rest next check", feed.id, unseen.len());
+    }
+
+    Selection { posts: unseen.into_iter().rev().take(max_posts).collect(), backlog }
+}
+
+// Whether a feed would post a conversation, going by its post mode, tags and filters
+fn wants(feed: &UserFeed, filters: &FilterSet, conversation: &Conversation) -> bool {
+    feed.shares(conversation.cafe.is_some())
+        && feed.tag_filter().allows(conversation.tag.as_ref().map(|tag| tag.id.as_str()))
+        && filters.allows(&html_decode(conversation.contents.clone()))
+}
+
+// Whether the feed already saw a conversation, or it is older than the last one seen
+fn is_caught_up(feed: &UserFeed, conversation: &Conversation) -> bool {
+    conversation.id == feed.last_post_id || conversation.date_created < feed.last_post_timestamp
+}
+
+// Tracks which feeds following a source still need older conversations
+struct CatchUp {
+    done: Vec<bool>,
+}
+
+impl CatchUp {
+    fn new(feeds: usize) -> CatchUp {
+        CatchUp { done: vec![false; feeds] }
+    }
+
+    // Looks at the next older conversation, returning whether any feed still needs it.
+    // Feeds are done once they're caught up, or for feeds that have never posted, once they have a conversation to start from
+    fn observe(&mut self, followers: &[(UserFeed, FilterSet)], conversation: &Conversation) -> bool {
+        let mut needed = false;
+        for ((feed, filters), done) in followers.iter().zip(self.done.iter_mut()) {
+            if *done { continue; }
+            if is_caught_up(feed, conversation) {
+                *done = true;
+                continue;
+            }
+
+            needed = true;
+            *done = feed.last_post_id == "0" && wants(feed, filters, conversation);
+        }
+
+        needed
+    }
+
+    // Adds the conversations of the next page that any feed needs, returning whether another page is needed
+    fn add_page(&mut self, followers: &[(UserFeed, FilterSet)], conversations: &mut Vec<Conversation>, page: Vec<Conversation>) -> bool {
+        let page_len = page.len();
+
+        for conversation in page {
+            // Posts shifting between pages, everything newer was already looked at
+            if conversations.iter().any(|seen| seen.id == conversation.id) {
+                self.finish();
+                return false;
+            }
+
+            if !self.observe(followers, &conversation) { return false; }
+            conversations.push(conversation);
+            if self.is_finished() { return false; }
+        }
+
+        // Reached the start of the source's history
+        if page_len < PAGE_SIZE as usize {
+            self.finish();
+            return false;
+        }
+
+        true
+    }
+
+    fn finish(&mut self) {
+        self.done.fill(true);
+    }
+
+    fn is_finished(&self) -> bool {
+        self.done.iter().all(|done| *done)
+    }
+}
+
+// Returns the source's conversations, newest first, going back to where every feed following it left off,
+// along with whether each feed had all of its new conversations looked at
+async fn fetch_source_conversations(data: &Data, followers: &[(UserFeed, FilterSet)]) -> Result<(Vec<Conversation>, Vec<bool>), BotCafeError> {
+    let Some((source, _)) = followers.first() else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+
+    let mut catch_up = CatchUp::new(followers.len());
+    let mut conversations: Vec<Conversation> = Vec::new();
+    let mut offset = 0;
+    for _ in 0..MAX_PAGES {
+        let page = match source.feed_type {
+            FeedType::User => data.client.account_conversations(&source.heycafe_id, None, PAGE_SIZE, offset).await?,
+            FeedType::Cafe => data.client.cafe_conversations(&source.heycafe_id, None, PAGE_SIZE, offset).await?
+        };
+
+        if !catch_up.add_page(followers, &mut conversations, page) { break; }
+        offset += PAGE_SIZE;
+    }
+
+    Ok((conversations, catch_up.done))
+}
+
+// Posts a single conversation to the feed's channel
+pub(crate) async fn post_conversation(ctx: &serenity::Context, feed: &UserFeed, settings: &GuildSettings, conversation: &Conversation) -> Result<(), BotCafeError> {
+    // Format message to post
+    let channel_id: ChannelId = ChannelId(feed.channel_id as u64);
+    let account = conversation.account.clone().unwrap_or_default();
+
+    // Discord rejects empty titles and fields, and Hey.Cafe sometimes leaves the account out
+    let cafe_title = conversation.cafe.as_ref()
+        .filter(|cafe| !cafe.name.is_empty())
+        .map(|cafe| format!("{} (!{})", cafe.name, cafe.alias));
+    let account_title = Some(&account)
+        .filter(|account| !account.name.is_empty())
+        .map(|account| format!("{} (@{})", account.name, account.alias));
+    let embed_author = match feed.feed_type {
+        FeedType::Cafe => cafe_title.or(account_title),
+        FeedType::User => account_title.or(cafe_title)
+    }.unwrap_or_else(|| String::from("Hey.Cafe conversation"));
+    let embed_author = console::truncate_str(&embed_author, 256, "...").to_string();
+
+    // Truncate last, decoding and escaping can change the length
+    let mut embed_desc = html_decode(conversation.contents.clone());
+    embed_desc = escpae_markdown(embed_desc);
+    if embed_desc.chars().count() >= 4096 {
+        embed_desc = console::truncate_str(&embed_desc, 4093, "...").to_string();
+    }
+
+    // User posts made inside a cafe say which cafe
+    let cafe_info = match (feed.feed_type, &conversation.cafe) {
+        (FeedType::User, Some(cafe)) if !cafe.alias.is_empty() => {
+            let name = if cafe.name.is_empty() { &cafe.alias } else { &cafe.name };
+            format!("[{name}](https://hey.cafe/!{})", cafe.alias)
+        },
+        _ => String::new()
+    };
+
+    let tag_info = match &conversation.tag {
+        Some(tag) if !feed.tag_filter().is_empty() => format!("{} {}", tag.emoji, tag.name).trim().to_string(),
+        _ => String::new()
+    };
+
+    let attachment_info = if !conversation.attachments.is_empty() {
+        String::from("Yes")
+    } else {
+        String::new()
+    };
+    let image_url = conversation.image_url();
+
+    let mention_text = if feed.mention_role_id != 0 {
+        format!("{}", Mention::from(RoleId(feed.mention_role_id as u64)))
+    } else {
+        String::new()
+    };
+
+    // Post content
+    channel_id.send_message(&ctx, |m| {
+        m.content(mention_text);
+        m.embed(|e| {
+            e.color(settings.embed_color as u32);
+            e.title(embed_author);
+            e.url(format!("https://hey.cafe/conversation/{}", conversation.id));
+            if let Some(avatar) = &account.avatar {
+                e.thumbnail(avatar);
+            }
+            if !embed_desc.trim().is_empty() {
+                e.description(embed_desc);
+            }
+            if !cafe_info.is_empty() {
+                e.field("Cafe:", cafe_info, true);
+            }
+            if !tag_info.is_empty() {
+                e.field("Tag:", tag_info, true);
+            }
+            if !attachment_info.is_empty() {
+                e.field("Attachments:", attachment_info, true);
+            }
+            if feed.feed_type == FeedType::Cafe && !account.name.is_empty() {
+                e.field("Author:", &account.name, true);
+            }
+            if let Some(image_url) = image_url {
+                e.image(image_url);
+            }
+            e.footer(|f|
+                f.text(format!("Shared to Discord at {}", Utc::now().with_timezone(&settings.tz()).format("%Y-%m-%d %H:%M:%S %Z")))
+            )
+        })
+    }).await?;
+
+    Ok(())
+}
+
+// Log a failed feed, noting whether it should recover on its own
+fn log_feed_error(feed: &UserFeed, err: &BotCafeError) {
+    let kind = if err.is_temporary() { "TEMPORARY" } else { "PERMANENT" };
+    println!("[FEED] {kind} FAILURE - Feed: {} - Guild: {} - Error: {}", feed.id, feed.guild_id, err);
+}
+
+// Log a feed that panicked so the rest of the check can carry on
+fn log_feed_panic(feed: &UserFeed, stage: &str) {
+    println!("[FEED] PANIC - Feed: {} - Guild: {} - Panicked while {stage}, skipping", feed.id, feed.guild_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use botcafe::client::{Cafe, Tag};
+    use botcafe::filter::{FilterAction, FilterKind};
+
+    // A feed that last saw the conversation with the given id
+    fn feed(feed_type: FeedType, last_seen: i64) -> UserFeed {
+        UserFeed {
+            id: 1,
+            guild_id: 1,
+            feed_type,
+            channel_id: 1,
+            heycafe_id: String::from("source"),
+            last_post_id: if last_seen == 0 { String::from("0") } else { last_seen.to_string() },
+            mention_role_id: 0,
+            last_post_timestamp: last_seen,
+            enabled: true,
+            consecutive_failures: 0,
+            last_error: None,
+            last_success_at: None,
+            retry_after: 0,
+            poll_interval_secs: None,
+            last_checked_at: 0,
+            include_tag_ids: String::new(),
+            exclude_tag_ids: String::new(),
+            post_mode: PostMode::Personal,
+        }
+    }
+
+    // Conversations use their id as their timestamp, so higher ids are newer
+    fn conversation(id: i64) -> Conversation {
+        Conversation { id: id.to_string(), date_created: id, contents: format!("Post {id}"), ..Default::default() }
+    }
+
+    fn tagged(id: i64, tag_id: &str) -> Conversation {
+        Conversation { tag: Some(Tag { id: tag_id.to_string(), ..Default::default() }), ..conversation(id) }
+    }
+
+    fn in_cafe(id: i64) -> Conversation {
+        Conversation { cafe: Some(Cafe { alias: String::from("cafe"), ..Default::default() }), ..conversation(id) }
+    }
+
+    // A source's history, newest first
+    fn history(newest: i64) -> Vec<Conversation> {
+        (1..=newest).rev().map(conversation).collect()
+    }
+
+    fn ids(conversations: &[&Conversation]) -> Vec<i64> {
+        conversations.iter().map(|conversation| conversation.date_created).collect()
+    }
+
+    // Feeds a history through the tracker a page at a time, the way the poller fetches it
+    fn fetch(followers: &[(UserFeed, FilterSet)], history: &[Conversation]) -> (Vec<Conversation>, Vec<bool>) {
+        let mut catch_up = CatchUp::new(followers.len());
+        let mut conversations = Vec::new();
+        for page in history.chunks(PAGE_SIZE as usize).take(MAX_PAGES as usize) {
+            if !catch_up.add_page(followers, &mut conversations, page.to_vec()) { break; }
+        }
+
+        (conversations, catch_up.done)
+    }
+
+    #[test]
+    fn stops_at_the_last_seen_conversation() {
+        let feed = feed(FeedType::Cafe, 7);
+        assert!(!is_caught_up(&feed, &conversation(8)));
+        assert!(is_caught_up(&feed, &conversation(7)));
+        assert!(is_caught_up(&feed, &conversation(6)));
+
+        let conversations = history(10);
+        let selection = new_conversations_for(&feed, &FilterSet::default(), &conversations, 5);
+        assert_eq!(ids(&selection.posts), [8, 9, 10]);
+        assert!(!selection.backlog);
+    }
+
+    #[test]
+    fn backlogs_post_oldest_first_over_several_checks() {
+        let conversations = history(13);
+        let selection = new_conversations_for(&feed(FeedType::Cafe, 5), &FilterSet::default(), &conversations, 5);
+        assert_eq!(ids(&selection.posts), [6, 7, 8, 9, 10]);
+        assert!(selection.backlog);
+
+        // Progress is saved at the last conversation posted
+        let selection = new_conversations_for(&feed(FeedType::Cafe, 10), &FilterSet::default(), &conversations, 5);
+        assert_eq!(ids(&selection.posts), [11, 12, 13]);
+        assert!(!selection.backlog);
+    }
+
+    #[test]
+    fn first_check_only_takes_the_newest_shared_conversation() {
+        let mut feed = feed(FeedType::Cafe, 0);
+        feed.include_tag_ids = String::from("2");
+        let conversations = vec![tagged(5, "1"), tagged(4, "2"), tagged(3, "2")];
+
+        let selection = new_conversations_for(&feed, &FilterSet::default(), &conversations, 5);
+        assert_eq!(ids(&selection.posts), [4]);
+        assert!(!selection.backlog);
+    }
+
+    #[test]
+    fn user_feeds_follow_their_post_mode() {
+        let conversations = vec![in_cafe(5), conversation(4), in_cafe(3)];
+        let mut user_feed = feed(FeedType::User, 1);
+
+        let selection = new_conversations_for(&user_feed, &FilterSet::default(), &conversations, 5);
+        assert_eq!(ids(&selection.posts), [4]);
+
+        user_feed.post_mode = PostMode::Cafe;
+        let selection = new_conversations_for(&user_feed, &FilterSet::default(), &conversations, 5);
+        assert_eq!(ids(&selection.posts), [3, 5]);
+
+        user_feed.post_mode = PostMode::Both;
+        let selection = new_conversations_for(&user_feed, &FilterSet::default(), &conversations, 5);
+        assert_eq!(ids(&selection.posts), [3, 4, 5]);
+
+        // Cafe feeds share everything posted in the cafe
+        let selection = new_conversations_for(&feed(FeedType::Cafe, 1), &FilterSet::default(), &conversations, 5);
+        assert_eq!(ids(&selection.posts), [3, 4, 5]);
+    }
+
+    #[test]
+    fn feeds_on_one_source_keep_their_own_filters() {
+        let conversations = vec![tagged(6, "1"), tagged(5, "2"), tagged(4, "1"), conversation(3)];
+
+        let mut tagged_feed = feed(FeedType::Cafe, 2);
+        tagged_feed.include_tag_ids = String::from("1");
+        let mut skipping_feed = feed(FeedType::Cafe, 2);
+        skipping_feed.exclude_tag_ids = String::from("1");
+        let keyword_feed = feed(FeedType::Cafe, 2);
+        let keywords = FilterSet::new([(FilterAction::Exclude, FilterKind::Keyword, "post 5")]).unwrap();
+
+        assert_eq!(ids(&new_conversations_for(&tagged_feed, &FilterSet::default(), &conversations, 5).posts), [4, 6]);
+        assert_eq!(ids(&new_conversations_for(&skipping_feed, &FilterSet::default(), &conversations, 5).posts), [3, 5]);
+        assert_eq!(ids(&new_conversations_for(&keyword_feed, &keywords, &conversations, 5).posts), [3, 4, 6]);
+    }
+
+    #[test]
+    fn pages_until_every_feed_is_caught_up() {
+        let followers = vec![
+            (feed(FeedType::Cafe, 28), FilterSet::default()),
+            (feed(FeedType::Cafe, 12), FilterSet::default()),
+        ];
+
+        let (conversations, done) = fetch(&followers, &history(30));
+        assert_eq!(conversations.len(), 18);
+        assert_eq!(conversations.last().unwrap().id, "13");
+        assert_eq!(done, [true, true]);
+    }
+
+    #[test]
+    fn pages_past_the_cap_for_feeds_with_rare_tags() {
+        // Only every tenth conversation has the feed's tag
+        let source: Vec<Conversation> = (1..=40).rev()
+            .map(|id| if id % 10 == 0 { tagged(id, "rare") } else { tagged(id, "common") })
+            .collect();
+        let mut rare_feed = feed(FeedType::Cafe, 5);
+        rare_feed.include_tag_ids = String::from("rare");
+        let followers = vec![(rare_feed, FilterSet::default())];
+
+        let (conversations, done) = fetch(&followers, &source);
+        assert_eq!(done, [true]);
+
+        let selection = new_conversations_for(&followers[0].0, &followers[0].1, &conversations, 2);
+        assert_eq!(ids(&selection.posts), [10, 20]);
+        assert!(selection.backlog);
+    }
+
+    #[test]
+    fn first_check_stops_at_the_first_shared_conversation() {
+        let mut new_feed = feed(FeedType::User, 0);
+        new_feed.post_mode = PostMode::Personal;
+        let source = vec![in_cafe(30), in_cafe(29), conversation(28), conversation(27)];
+        let followers = vec![(new_feed, FilterSet::default())];
+
+        let mut catch_up = CatchUp::new(1);
+        let mut conversations = Vec::new();
+        assert!(!catch_up.add_page(&followers, &mut conversations, source));
+        assert_eq!(conversations.len(), 3);
+        assert_eq!(catch_up.done, [true]);
+    }
+
+    #[test]
+    fn safety_cap_leaves_feeds_unfinished() {
+        // A new feed whose filters match nothing would otherwise read the whole history
+        let keywords = FilterSet::new([(FilterAction::Include, FilterKind::Keyword, "never")]).unwrap();
+        let followers = vec![(feed(FeedType::Cafe, 0), keywords)];
+
+        let (conversations, done) = fetch(&followers, &history(1000));
+        assert_eq!(conversations.len(), (MAX_PAGES * PAGE_SIZE) as usize);
+        assert_eq!(done, [false]);
+    }
+
+    #[test]
+    fn short_or_shifted_pages_finish_the_fetch() {
+        let followers = vec![(feed(FeedType::Cafe, 0), FilterSet::new([(FilterAction::Include, FilterKind::Keyword, "never")]).unwrap())];
+
+        let (conversations, done) = fetch(&followers, &history(15));
+        assert_eq!(conversations.len(), 15);
+        assert_eq!(done, [true]);
+
+        // The same conversations showing up again on the next page
+        let mut catch_up = CatchUp::new(1);
+        let mut conversations = Vec::new();
+        let page: Vec<Conversation> = history(20).into_iter().take(PAGE_SIZE as usize).collect();
+        assert!(catch_up.add_page(&followers, &mut conversations, page.clone()));
+        assert!(!catch_up.add_page(&followers, &mut conversations, page));
+        assert_eq!(conversations.len(), PAGE_SIZE as usize);
+        assert_eq!(catch_up.done, [true]);
+    }
+}
@@ -0,0 +1,54 @@
+// Token bucket used to limit requests to Hey.Cafe
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket holding `capacity` tokens, refilled at `refill_per_sec` tokens per second.
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        let capacity = f64::from(capacity.max(1));
+
+        Self {
+            capacity,
+            refill_per_sec: refill_per_sec.max(0.01),
+            state: Mutex::new(BucketState {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a token is available and takes it.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+                state.tokens = (state.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+                state.last_refill = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - state.tokens) / self.refill_per_sec)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,235 @@
+// Guild configuration commands
+use crate::{Context, Error, GuildSettings, DEFAULT_EMBED_COLOR, MIN_POLL_INTERVAL_SECS};
+use poise::serenity_prelude as serenity;
+use serenity::{ChannelId, Mentionable, RoleId};
+use botcafe::BotCafeError;
+
+// PARENT
+#[poise::command(
+    slash_command,
+    subcommands("view", "managerrole", "mentionrole", "color", "timezone", "logchannel", "interval"),
+    required_permissions = "MANAGE_GUILD",
+    guild_only,
+)]
+pub async fn settings(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show this server's settings.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_GUILD",
+    guild_only,
+)]
+pub async fn view(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = *ctx.guild_id().unwrap().as_u64() as i64;
+    let settings = GuildSettings::load(&ctx.data().database, guild_id).await?;
+
+    let role_text = |role_id: i64| if role_id != 0 { format!("{}", RoleId(role_id as u64).mention()) } else { String::from("None") };
+    let manager_role = role_text(settings.feed_settings_required_roleid);
+    let mention_role = role_text(settings.default_mention_role_id);
+
+    let log_channel = if settings.admin_channel_id != 0 {
+        format!("{}", ChannelId(settings.admin_channel_id as u64).mention())
+    } else {
+        String::from("Feed channel")
+    };
+
+    let interval = match settings.poll_interval_secs {
+        Some(secs) => format!("{secs} seconds"),
+        None => format!("{} seconds (default)", ctx.data().default_poll_interval_secs)
+    };
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.title("Server Settings");
+            e.color(settings.embed_color as u32);
+            e.field("Feed manager role:", manager_role, true);
+            e.field("Default mention role:", mention_role, true);
+            e.field("Embed color:", format!("#{:06x}", settings.embed_color), true);
+            e.field("Timezone:", &settings.timezone, true);
+            e.field("Log channel:", log_channel, true);
+            e.field("Poll interval:", interval, true)
+        })
+    }).await?;
+
+    Ok(())
+}
+
+/// Set a role that can manage feeds without the Manage Channels permission.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_GUILD",
+    guild_only,
+)]
+pub async fn managerrole(
+    ctx: Context<'_>,
+    #[description = "Role that can manage feeds, leave empty to clear it."] role: Option<serenity::Role>
+) -> Result<(), Error> {
+    let guild_id = ensure_settings(ctx).await?;
+    let role_id = role.as_ref().map(|role| *role.id.as_u64() as i64).unwrap_or(0);
+
+    sqlx::query!("UPDATE guild_settings SET feed_settings_required_roleid = ? WHERE guild_id = ?", role_id, guild_id)
+        .execute(&ctx.data().database)
+        .await?;
+
+    let msg = match role {
+        Some(role) => format!("Members with the role {} can now manage feeds!", role.name),
+        None => String::from("Only members with the Manage Channels permission can manage feeds now!")
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Set the role new feeds mention when no role is given.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_GUILD",
+    guild_only,
+)]
+pub async fn mentionrole(
+    ctx: Context<'_>,
+    #[description = "Role to mention by default, leave empty to clear it."] role: Option<serenity::Role>
+) -> Result<(), Error> {
+    let guild_id = ensure_settings(ctx).await?;
+    let role_id = role.as_ref().map(|role| *role.id.as_u64() as i64).unwrap_or(0);
+
+    sqlx::query!("UPDATE guild_settings SET default_mention_role_id = ? WHERE guild_id = ?", role_id, guild_id)
+        .execute(&ctx.data().database)
+        .await?;
+
+    let msg = match role {
+        Some(role) => format!("New feeds will mention {} unless another role is picked!", role.name),
+        None => String::from("New feeds won't mention a role unless one is picked!")
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Set the color of feed posts.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_GUILD",
+    guild_only,
+)]
+pub async fn color(
+    ctx: Context<'_>,
+    #[description = "Hex color like #604fd8, leave empty to reset it."]
+    #[max_length = 7] color: Option<String>
+) -> Result<(), Error> {
+    let guild_id = ensure_settings(ctx).await?;
+
+    let embed_color = match &color {
+        Some(color) => i64::from_str_radix(color.trim_start_matches('#'), 16)
+            .ok()
+            .filter(|value| (0..=0xffffff).contains(value))
+            .ok_or_else(|| BotCafeError::InvalidInput(format!("\"{color}\" isn't a hex color! Try something like #604fd8.")))?,
+        None => DEFAULT_EMBED_COLOR
+    };
+
+    sqlx::query!("UPDATE guild_settings SET embed_color = ? WHERE guild_id = ?", embed_color, guild_id)
+        .execute(&ctx.data().database)
+        .await?;
+
+    let msg = format!("Feed posts will now use the color #{embed_color:06x}!");
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Set the timezone used for times in feed posts.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_GUILD",
+    guild_only,
+)]
+pub async fn timezone(
+    ctx: Context<'_>,
+    #[description = "Timezone name like Europe/London, leave empty to use UTC."]
+    #[max_length = 50] timezone: Option<String>
+) -> Result<(), Error> {
+    let guild_id = ensure_settings(ctx).await?;
+
+    let tz = match &timezone {
+        Some(timezone) => timezone.parse::<chrono_tz::Tz>()
+            .map_err(|_| BotCafeError::InvalidInput(format!("\"{timezone}\" isn't a timezone I know! Try a name like Europe/London or America/New_York.")))?,
+        None => chrono_tz::UTC
+    };
+    let tz_name = tz.name();
+
+    sqlx::query!("UPDATE guild_settings SET timezone = ? WHERE guild_id = ?", tz_name, guild_id)
+        .execute(&ctx.data().database)
+        .await?;
+
+    let msg = format!("Feed posts will now show times in {tz_name}!");
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Set the channel for bot notices, like feeds being disabled.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_GUILD",
+    guild_only,
+)]
+pub async fn logchannel(
+    ctx: Context<'_>,
+    #[description = "Channel for notices, leave empty to use each feed's channel."] channel: Option<serenity::Channel>
+) -> Result<(), Error> {
+    let guild_id = ensure_settings(ctx).await?;
+    let channel_id = channel.as_ref().map(|channel| *channel.id().as_u64() as i64).unwrap_or(0);
+
+    sqlx::query!("UPDATE guild_settings SET admin_channel_id = ? WHERE guild_id = ?", channel_id, guild_id)
+        .execute(&ctx.data().database)
+        .await?;
+
+    let msg = match channel {
+        Some(channel) => format!("Bot notices will now be posted in {channel}!"),
+        None => String::from("Bot notices will now be posted in the affected feed's channel!")
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+/// Set how often this server's feeds are checked.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_GUILD",
+    guild_only,
+)]
+pub async fn interval(
+    ctx: Context<'_>,
+    #[description = "Seconds between checks, leave empty to use the default."]
+    #[min = 30]
+    #[max = 86400] seconds: Option<i64>
+) -> Result<(), Error> {
+    let guild_id = ensure_settings(ctx).await?;
+    let seconds = seconds.map(|seconds| seconds.max(MIN_POLL_INTERVAL_SECS));
+
+    sqlx::query!("UPDATE guild_settings SET poll_interval_secs = ? WHERE guild_id = ?", seconds, guild_id)
+        .execute(&ctx.data().database)
+        .await?;
+
+    let msg = match seconds {
+        Some(seconds) => format!("Feeds will now be checked every {seconds} seconds, unless a feed has its own interval!"),
+        None => format!("Feeds will now be checked every {} seconds, unless a feed has its own interval!", ctx.data().default_poll_interval_secs)
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
+
+// FUNCTION - Makes sure the guild has a settings row and returns its id
+async fn ensure_settings(ctx: Context<'_>) -> Result<i64, Error> {
+    let guild_id = *ctx.guild_id().unwrap().as_u64() as i64;
+
+    sqlx::query!("INSERT OR IGNORE INTO guild_settings (guild_id) VALUES (?)", guild_id)
+        .execute(&ctx.data().database)
+        .await?;
+
+    Ok(guild_id)
+}
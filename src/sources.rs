@@ -0,0 +1,130 @@
+// Local cache of the users and cafes feeds follow
+use crate::{Data, Error};
+use chrono::prelude::*;
+use sqlx::SqlitePool;
+use botcafe::client::{HeyCafeClient, Tag};
+use botcafe::FeedType;
+
+// How old cached names can get before the poller looks them up again
+const REFRESH_AFTER_SECS: i64 = 60 * 60 * 24;
+
+// Details of a user or cafe from Hey.Cafe
+pub struct SourceInfo {
+    pub id: String,
+    pub alias: String,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub tags: Vec<Tag>,
+}
+
+// FUNCTION - Looks up a user or cafe by alias or id
+pub async fn lookup(client: &HeyCafeClient, feed_type: FeedType, query: &str) -> Result<SourceInfo, Error> {
+    let info = match feed_type {
+        FeedType::Cafe => {
+            let cafe = client.cafe_info(query).await?;
+            SourceInfo { id: cafe.id, alias: cafe.alias, name: cafe.name, avatar: cafe.avatar, tags: cafe.tags }
+        },
+        FeedType::User => {
+            let account = client.account_info(query).await?;
+            SourceInfo { id: account.id, alias: account.alias, name: account.name, avatar: account.avatar, tags: account.tags }
+        }
+    };
+
+    Ok(info)
+}
+
+// FUNCTION - Finds a user or cafe by alias in the cache, preferring ones the guild follows, and only asks Hey.Cafe when it isn't cached.
+// Feeds for sources that were deleted or renamed on Hey.Cafe can still be found this way
+pub async fn resolve(data: &Data, guild_id: i64, feed_type: FeedType, alias: &str) -> Result<SourceInfo, Error> {
+    let cached = sqlx::query!(
+        "SELECT heycafe_id, alias, display_name, avatar FROM heycafe_sources
+        WHERE feed_type = ? AND alias = ? COLLATE NOCASE
+        ORDER BY EXISTS (SELECT 1 FROM heycafe_feeds WHERE heycafe_feeds.guild_id = ? AND heycafe_feeds.feed_type = heycafe_sources.feed_type AND heycafe_feeds.heycafe_id = heycafe_sources.heycafe_id) DESC, updated_at DESC
+        LIMIT 1",
+        feed_type, alias, guild_id)
+        .fetch_optional(&data.database)
+        .await?;
+
+    let Some(cached) = cached else {
+        let info = lookup(&data.client, feed_type, alias).await?;
+        save(&data.database, feed_type, &info).await?;
+        return Ok(info);
+    };
+
+    let tags = sqlx::query!("SELECT tag_id, name, emoji FROM heycafe_tags WHERE feed_type = ? AND heycafe_id = ? ORDER BY name", feed_type, cached.heycafe_id)
+        .fetch_all(&data.database)
+        .await?
+        .into_iter()
+        .map(|tag| Tag { id: tag.tag_id, name: tag.name, emoji: tag.emoji })
+        .collect();
+
+    Ok(SourceInfo { id: cached.heycafe_id, alias: cached.alias, name: cached.display_name, avatar: cached.avatar, tags })
+}
+
+// FUNCTION - Saves the names of a user or cafe and its tags
+pub async fn save(database: &SqlitePool, feed_type: FeedType, info: &SourceInfo) -> Result<(), Error> {
+    let now = Utc::now().timestamp();
+
+    sqlx::query!("INSERT INTO heycafe_sources (feed_type, heycafe_id, alias, display_name, avatar, updated_at) VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT (feed_type, heycafe_id) DO UPDATE SET alias = excluded.alias, display_name = excluded.display_name, avatar = excluded.avatar, updated_at = excluded.updated_at",
+        feed_type, info.id, info.alias, info.name, info.avatar, now)
+        .execute(database)
+        .await?;
+
+    for tag in &info.tags {
+        sqlx::query!("INSERT INTO heycafe_tags (feed_type, heycafe_id, tag_id, name, emoji) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (feed_type, heycafe_id, tag_id) DO UPDATE SET name = excluded.name, emoji = excluded.emoji",
+            feed_type, info.id, tag.id, tag.name, tag.emoji)
+            .execute(database)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// FUNCTION - Looks a source up again when its cached names are missing or old
+pub async fn refresh_if_stale(data: &Data, feed_type: FeedType, heycafe_id: &str) {
+    let stale_before = Utc::now().timestamp() - REFRESH_AFTER_SECS;
+    let cached = sqlx::query!("SELECT updated_at FROM heycafe_sources WHERE feed_type = ? AND heycafe_id = ?", feed_type, heycafe_id)
+        .fetch_optional(&data.database)
+        .await;
+
+    match cached {
+        Ok(Some(cached)) if cached.updated_at > stale_before => return,
+        Ok(_) => {},
+        Err(err) => {
+            println!("[SOURCE] Failed to read cached source - Source: {feed_type} {heycafe_id} - Error: {err}");
+            return;
+        }
+    }
+
+    let refreshed = match lookup(&data.client, feed_type, heycafe_id).await {
+        Ok(info) => save(&data.database, feed_type, &info).await,
+        Err(err) => Err(err)
+    };
+
+    if let Err(err) = refreshed {
+        println!("[SOURCE] Failed to refresh source - Source: {feed_type} {heycafe_id} - Error: {err}");
+    }
+}
+
+// FUNCTION - Tag names of a user or cafe, from the cache when it's fresh
+pub async fn tag_names(data: &Data, feed_type: FeedType, alias: &str) -> Result<Vec<String>, Error> {
+    let stale_before = Utc::now().timestamp() - REFRESH_AFTER_SECS;
+    let cached = sqlx::query!("SELECT heycafe_id, updated_at FROM heycafe_sources WHERE feed_type = ? AND alias = ? COLLATE NOCASE", feed_type, alias)
+        .fetch_optional(&data.database)
+        .await?;
+
+    if let Some(cached) = cached.filter(|cached| cached.updated_at > stale_before) {
+        let tags = sqlx::query!("SELECT name FROM heycafe_tags WHERE feed_type = ? AND heycafe_id = ? ORDER BY name", feed_type, cached.heycafe_id)
+            .fetch_all(&data.database)
+            .await?;
+
+        return Ok(tags.into_iter().map(|tag| tag.name).collect());
+    }
+
+    let info = lookup(&data.client, feed_type, alias).await?;
+    save(&data.database, feed_type, &info).await?;
+
+    Ok(info.tags.into_iter().map(|tag| tag.name).collect())
+}